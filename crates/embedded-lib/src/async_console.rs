@@ -0,0 +1,173 @@
+use core::fmt;
+
+use embedded_io_async::{Read, Write};
+use log::debug;
+
+use crate::console::{zeroize, Console, InputHandler, InputNewline, OutputNewline};
+use crate::history::History;
+use crate::transport::Transport;
+
+// Transport the async console puts between its Console and the real port.
+// The received bytes are handed to Console one by one and everything the
+// Console writes is staged until it can be written asynchronously.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StagedError {
+    // the output of one received chunk does not fit in `tx_buffer`
+    Full,
+}
+
+impl fmt::Display for StagedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StagedError::Full => write!(f, "output buffer full"),
+        }
+    }
+}
+
+pub struct Staged {
+    rx: Option<u8>,
+    tx: &'static mut [u8],
+    tx_len: usize,
+    dropped: usize,
+}
+
+impl Transport for Staged {
+    type Error = StagedError;
+
+    fn read(&mut self) -> Result<Option<u8>, StagedError> {
+        Ok(self.rx.take())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), StagedError> {
+        let n = bytes.len().min(self.tx.len() - self.tx_len);
+        self.tx[self.tx_len..self.tx_len + n].copy_from_slice(&bytes[..n]);
+        self.tx_len += n;
+        if n < bytes.len() {
+            self.dropped += bytes.len() - n;
+            return Err(StagedError::Full);
+        }
+        Ok(())
+    }
+}
+
+// Console driven by an embedded-io-async port. The pager and XON/XOFF
+// flow control are not supported: their waits read the port without
+// awaiting and would never see a byte.
+pub struct AsyncConsole<IO, InputStr>
+where
+    IO: Read + Write,
+    InputStr: InputHandler
+{
+    io: IO,
+    console: Console<Staged, InputStr>,
+    newline: OutputNewline,
+}
+
+impl<IO, InputStr> AsyncConsole<IO, InputStr>
+where
+    IO: Read + Write,
+    InputStr: InputHandler
+{
+    // `tx_buffer` holds the output of one received chunk: the echo, the
+    // redraw and the output of a command run by input_str. Output which
+    // does not fit is dropped, the writes of the command return
+    // fmt::Error from then on and the line is redrawn afterwards.
+    pub fn new(buffer: &'static mut [u8],
+               tx_buffer: &'static mut [u8],
+               prompt: &'static str,
+               io: IO,
+               input_str: Option<InputStr>) -> Self {
+        let staged = Staged { rx: None, tx: tx_buffer, tx_len: 0, dropped: 0 };
+        AsyncConsole {
+            io,
+            console: Console::with_transport(buffer, prompt, staged, input_str),
+            newline: OutputNewline::LfCr,
+        }
+    }
+
+    pub fn set_history(&mut self, history: History) {
+        self.console.set_history(history);
+    }
+
+    pub fn set_prompt(&mut self, prompt: &'static str) {
+        self.console.set_prompt(prompt);
+    }
+
+    pub async fn redraw(&mut self) -> Result<(), IO::Error> {
+        let _ = self.console.redraw();
+        self.write_staged().await
+    }
+
+    pub fn set_newline(&mut self, input: InputNewline, output: OutputNewline) {
+        self.console.set_newline(input, output);
+        self.newline = output;
+    }
+
+    pub fn io(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    pub fn handler(&mut self) -> Option<&mut InputStr> {
+        self.console.handler()
+    }
+
+    async fn write_staged(&mut self) -> Result<(), IO::Error> {
+        let dropped = core::mem::take(&mut self.console.transport().dropped);
+        self.write_tx().await?;
+        // the prompt went with the end of the output, the line is
+        // redrawn below what got through
+        if dropped > 0 && !self.console.is_running() {
+            debug!("console dropped {} bytes of output", dropped);
+            let _ = self.console.transport().write(self.newline.as_bytes());
+            let _ = self.console.redraw();
+            self.console.transport().dropped = 0;
+            self.write_tx().await?;
+        }
+        Ok(())
+    }
+
+    async fn write_tx(&mut self) -> Result<(), IO::Error> {
+        let staged = self.console.transport();
+        if staged.tx_len > 0 {
+            let len = staged.tx_len;
+            staged.tx_len = 0;
+            self.io.write_all(&self.console.transport().tx[..len]).await?;
+            self.io.flush().await?;
+        }
+        Ok(())
+    }
+
+    // Waits for input and handles everything received at once.
+    pub async fn input(&mut self) -> Result<(), IO::Error> {
+        self.write_staged().await?;
+        let mut rx = [0u8; 16];
+        let n = self.io.read(&mut rx).await?;
+        for &c in &rx[..n] {
+            self.console.transport().rx = Some(c);
+            let _ = self.console.input();
+        }
+        // the chunk may have held a secret
+        zeroize(&mut rx);
+        self.write_staged().await
+    }
+
+    // Polls a running command without waiting for input, see
+    // Output::keep_running. input only returns once something has been
+    // received, so the caller calls this on its own tick while
+    // is_running, e.g. racing input against a timer.
+    pub async fn poll(&mut self) -> Result<(), IO::Error> {
+        let _ = self.console.input();
+        self.write_staged().await
+    }
+
+    // A command started by input_str is still running, see poll.
+    pub fn is_running(&self) -> bool {
+        self.console.is_running()
+    }
+
+    // Prints a message above the line being edited, see Console::print_above.
+    pub async fn output(&mut self, message: &str) -> Result<(), IO::Error> {
+        let _ = self.console.print_above(message);
+        self.write_staged().await
+    }
+}
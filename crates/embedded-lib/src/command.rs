@@ -0,0 +1,579 @@
+use core::cell::{RefCell, RefMut};
+use core::fmt::{self, Write};
+
+use crate::console::{InputHandler, Output};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    UnknownCommand,
+    MissingArgument(usize),
+    InvalidArgument(usize),
+    TooManyArguments,
+    UnterminatedQuote,
+    Failed(&'static str),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand => write!(f, "unknown command"),
+            CommandError::MissingArgument(n) => write!(f, "missing argument #{}", n),
+            CommandError::InvalidArgument(n) => write!(f, "invalid argument #{}", n),
+            CommandError::TooManyArguments => write!(f, "too many arguments"),
+            CommandError::UnterminatedQuote => write!(f, "unterminated quote"),
+            CommandError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+pub type CommandFn<C> = fn(&mut C, &mut Args, &mut Output) -> Result<(), CommandError>;
+// Gives the candidates of the argument after the words in Args.
+pub type CompleteFn<C> = fn(&C, &mut Args, &mut dyn FnMut(&'static str));
+// Receives the line read after the command called Output::read_secret.
+pub type SecretFn<C> = fn(&mut C, &str, &mut Output) -> Result<(), CommandError>;
+// Runs the command again while it asks to, see Output::keep_running.
+pub type PollFn<C> = fn(&mut C, &mut Output) -> Result<(), CommandError>;
+// Writes the prompt, `last_error` is the error of the last command.
+pub type PromptFn<C> = fn(&C, Option<CommandError>, &mut Output);
+
+pub struct Command<C> {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub run: CommandFn<C>,
+    pub complete: Option<CompleteFn<C>>,
+    pub secret: Option<SecretFn<C>>,
+    pub poll: Option<PollFn<C>>,
+}
+
+impl<C> Command<C> {
+    pub const fn new(name: &'static str, help: &'static str, run: CommandFn<C>) -> Self {
+        Command { name, help, run, complete: None, secret: None, poll: None }
+    }
+
+    pub const fn with_complete(self, complete: CompleteFn<C>) -> Self {
+        Command { complete: Some(complete), ..self }
+    }
+
+    pub const fn with_secret(self, secret: SecretFn<C>) -> Self {
+        Command { secret: Some(secret), ..self }
+    }
+
+    pub const fn with_poll(self, poll: PollFn<C>) -> Self {
+        Command { poll: Some(poll), ..self }
+    }
+}
+
+pub trait FromArg<'a>: Sized {
+    fn from_arg(arg: &'a str) -> Option<Self>;
+}
+
+impl<'a> FromArg<'a> for &'a str {
+    fn from_arg(arg: &'a str) -> Option<Self> {
+        Some(arg)
+    }
+}
+
+impl FromArg<'_> for bool {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "1" | "on" | "true" | "yes" | "enable" => Some(true),
+            "0" | "off" | "false" | "no" | "disable" => Some(false),
+            _ => None
+        }
+    }
+}
+
+fn split_radix(arg: &str) -> (&str, u32) {
+    if let Some(digits) = arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        (digits, 16)
+    }
+    else if let Some(digits) = arg.strip_prefix("0b").or_else(|| arg.strip_prefix("0B")) {
+        (digits, 2)
+    }
+    else {
+        (arg, 10)
+    }
+}
+
+macro_rules! from_arg_unsigned {
+    ($($t:ty),*) => {
+        $(
+            impl FromArg<'_> for $t {
+                fn from_arg(arg: &str) -> Option<Self> {
+                    let (digits, radix) = split_radix(arg);
+                    <$t>::from_str_radix(digits, radix).ok()
+                }
+            }
+        )*
+    }
+}
+
+macro_rules! from_arg_signed {
+    ($($t:ty),*) => {
+        $(
+            impl FromArg<'_> for $t {
+                fn from_arg(arg: &str) -> Option<Self> {
+                    let (negative, arg) = match arg.strip_prefix('-') {
+                        Some(arg) => (true, arg),
+                        None => (false, arg)
+                    };
+                    let (digits, radix) = split_radix(arg);
+                    if digits.starts_with(['+', '-']) { return None; }
+                    // parse with the sign to accept MIN
+                    let value = i128::from_str_radix(digits, radix).ok()?;
+                    let value = if negative { -value } else { value };
+                    <$t>::try_from(value).ok()
+                }
+            }
+        )*
+    }
+}
+
+from_arg_unsigned!(u8, u16, u32, u64, usize);
+from_arg_signed!(i8, i16, i32, i64, isize);
+
+// Splits a command line into whitespace separated words.
+// A word may be quoted with '"' or '\'' to keep white spaces in it.
+pub struct Args<'a> {
+    rest: &'a str,
+    index: usize,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Args { rest: line, index: 0 }
+    }
+
+    // Number of words already taken, the command name is #0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn next_str(&mut self) -> Result<Option<&'a str>, CommandError> {
+        let rest = self.rest.trim_start();
+        let first = match rest.chars().next() {
+            Some(c) => c,
+            None => {
+                self.rest = rest;
+                return Ok(None);
+            }
+        };
+        let (word, rest) = if first == '"' || first == '\'' {
+            let quoted = &rest[1..];
+            match quoted.find(first) {
+                Some(end) => (&quoted[..end], &quoted[end+1..]),
+                None => return Err(CommandError::UnterminatedQuote)
+            }
+        }
+        else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        self.rest = rest;
+        self.index += 1;
+        Ok(Some(word))
+    }
+
+    pub fn optional<T: FromArg<'a>>(&mut self) -> Result<Option<T>, CommandError> {
+        match self.next_str()? {
+            Some(word) => match T::from_arg(word) {
+                Some(value) => Ok(Some(value)),
+                None => Err(CommandError::InvalidArgument(self.index - 1))
+            },
+            None => Ok(None)
+        }
+    }
+
+    pub fn required<T: FromArg<'a>>(&mut self) -> Result<T, CommandError> {
+        let index = self.index;
+        self.optional()?.ok_or(CommandError::MissingArgument(index))
+    }
+
+    // Remaining text of the line without parsing it.
+    pub fn rest(&self) -> &'a str {
+        self.rest.trim()
+    }
+
+    pub fn finish(&mut self) -> Result<(), CommandError> {
+        if self.rest.trim().is_empty() { Ok(()) } else { Err(CommandError::TooManyArguments) }
+    }
+}
+
+fn find<'a, C>(commands: &'a [Command<C>], name: &str) -> Option<&'a Command<C>> {
+    commands.iter().find(|command| command.name == name)
+}
+
+fn help<C>(commands: &[Command<C>], args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    if let Some(name) = args.next_str()? {
+        args.finish()?;
+        return match find(commands, name) {
+            Some(command) => { let _ = writeln!(output, "{}", command.help); Ok(()) },
+            None if name == "help" => { let _ = writeln!(output, "help [command]"); Ok(()) },
+            None => Err(CommandError::UnknownCommand)
+        };
+    }
+    let width = commands.iter().map(|command| command.name.len()).fold(4, usize::max);
+    let _ = writeln!(output, "{:width$}  show this help", "help", width = width);
+    for command in commands {
+        let _ = writeln!(output, "{:width$}  {}", command.name, command.help, width = width);
+    }
+    Ok(())
+}
+
+fn complete<C>(commands: &[Command<C>], context: &C, line: &str, candidate: &mut dyn FnMut(&'static str)) {
+    // only the words before the one under the cursor are passed on
+    let done = &line[..line.rfind(' ').map_or(0, |p| p + 1)];
+    let mut args = Args::new(done);
+    match args.next_str() {
+        Ok(Some(name)) => {
+            if let Some(Command { complete: Some(complete), .. }) = find(commands, name) {
+                (complete)(context, &mut args, candidate);
+            }
+        },
+        Ok(None) => {
+            candidate("help");
+            for command in commands {
+                candidate(command.name);
+            }
+        },
+        Err(_) => ()
+    }
+}
+
+// What a shell keeps for one operator.
+struct State<'a, C> {
+    // command waiting for the secret it asked for
+    secret: Option<&'a Command<C>>,
+    // command which keeps running
+    running: Option<&'a Command<C>>,
+    prompt: Option<PromptFn<C>>,
+    last_error: Option<CommandError>,
+}
+
+impl<'a, C> State<'a, C> {
+    const fn new() -> Self {
+        State { secret: None, running: None, prompt: None, last_error: None }
+    }
+
+    fn execute(&mut self, commands: &'a [Command<C>], context: &mut C, line: &str, output: &mut Output) -> Result<(), CommandError> {
+        let mut args = Args::new(line);
+        let name = match args.next_str()? {
+            Some(name) => name,
+            None => return Ok(())
+        };
+        match find(commands, name) {
+            Some(command) => {
+                let result = (command.run)(context, &mut args, output);
+                if output.secret_requested() {
+                    self.secret = Some(command);
+                }
+                if output.running() {
+                    self.running = Some(command);
+                }
+                result
+            },
+            None if name == "help" => help(commands, &mut args, output),
+            None => Err(CommandError::UnknownCommand)
+        }
+    }
+
+    fn input_str(&mut self, commands: &'a [Command<C>], context: &mut C, line: &str, output: &mut Output) {
+        let result = self.execute(commands, context, line, output);
+        self.last_error = result.err();
+        if let Err(e) = result {
+            let name = Args::new(line).next_str().ok().flatten().unwrap_or("");
+            let _ = writeln!(output, "{}: {}", name, e);
+        }
+    }
+
+    fn input_secret(&mut self, context: &mut C, secret: &str, output: &mut Output) {
+        if let Some(command) = self.secret.take() {
+            let result = match command.secret {
+                Some(secret_fn) => (secret_fn)(context, secret, output),
+                None => Ok(()),
+            };
+            if output.secret_requested() {
+                self.secret = Some(command);
+            }
+            self.last_error = result.err();
+            if let Err(e) = result {
+                let _ = writeln!(output, "{}: {}", command.name, e);
+            }
+        }
+    }
+
+    fn poll(&mut self, context: &mut C, output: &mut Output) {
+        if let Some(command) = self.running.take() {
+            let result = match command.poll {
+                Some(poll_fn) => (poll_fn)(context, output),
+                None => Ok(()),
+            };
+            if output.running() {
+                self.running = Some(command);
+            }
+            self.last_error = result.err();
+            if let Err(e) = result {
+                let _ = writeln!(output, "{}: {}", command.name, e);
+            }
+        }
+    }
+
+    fn prompt(&self, context: &C, prompt: &str, output: &mut Output) {
+        match self.prompt {
+            Some(prompt_fn) => (prompt_fn)(context, self.last_error, output),
+            None => output.write_bytes(prompt.as_bytes()),
+        }
+    }
+}
+
+pub struct Shell<'a, C> {
+    commands: &'a [Command<C>],
+    context: C,
+    state: State<'a, C>,
+}
+
+impl<'a, C> Shell<'a, C> {
+    pub fn new(commands: &'a [Command<C>], context: C) -> Self {
+        Shell { commands, context, state: State::new() }
+    }
+
+    pub fn with_prompt(mut self, prompt: PromptFn<C>) -> Self {
+        self.state.prompt = Some(prompt);
+        self
+    }
+
+    pub fn context(&mut self) -> &mut C {
+        &mut self.context
+    }
+
+    pub fn commands(&self) -> &'a [Command<C>] {
+        self.commands
+    }
+
+    // Error of the last command, None if it succeeded.
+    pub fn last_error(&self) -> Option<CommandError> {
+        self.state.last_error
+    }
+
+    pub fn find(&self, name: &str) -> Option<&'a Command<C>> {
+        find(self.commands, name)
+    }
+
+    pub fn execute(&mut self, line: &str, output: &mut Output) -> Result<(), CommandError> {
+        self.state.execute(self.commands, &mut self.context, line, output)
+    }
+}
+
+impl<C> InputHandler for Shell<'_, C> {
+    fn input_str(&mut self, line: &str, output: &mut Output) {
+        self.state.input_str(self.commands, &mut self.context, line, output);
+    }
+
+    fn input_secret(&mut self, secret: &str, output: &mut Output) {
+        self.state.input_secret(&mut self.context, secret, output);
+    }
+
+    fn poll(&mut self, output: &mut Output) {
+        self.state.poll(&mut self.context, output);
+    }
+
+    fn prompt(&mut self, prompt: &str, output: &mut Output) {
+        self.state.prompt(&self.context, prompt, output);
+    }
+
+    fn complete(&mut self, line: &str, candidate: &mut dyn FnMut(&'static str)) {
+        complete(self.commands, &self.context, line, candidate);
+    }
+}
+
+// One command table and context used from several consoles, e.g. the
+// UART and the USB CDC port. Each console gets its own Session so that
+// a secret, a running command or the last error stay with the operator
+// who caused them, and the output goes to that console.
+pub struct SharedShell<'a, C> {
+    commands: &'a [Command<C>],
+    context: RefCell<C>,
+}
+
+impl<'a, C> SharedShell<'a, C> {
+    pub const fn new(commands: &'a [Command<C>], context: C) -> Self {
+        SharedShell { commands, context: RefCell::new(context) }
+    }
+
+    pub fn session(&self) -> Session<'_, 'a, C> {
+        Session { shell: self, state: State::new() }
+    }
+
+    // Panics while a command is running, like RefCell::borrow_mut.
+    pub fn context(&self) -> RefMut<'_, C> {
+        self.context.borrow_mut()
+    }
+
+    pub fn commands(&self) -> &'a [Command<C>] {
+        self.commands
+    }
+}
+
+pub struct Session<'s, 'a, C> {
+    shell: &'s SharedShell<'a, C>,
+    state: State<'a, C>,
+}
+
+impl<C> Session<'_, '_, C> {
+    pub fn with_prompt(mut self, prompt: PromptFn<C>) -> Self {
+        self.state.prompt = Some(prompt);
+        self
+    }
+
+    pub fn last_error(&self) -> Option<CommandError> {
+        self.state.last_error
+    }
+}
+
+impl<C> InputHandler for Session<'_, '_, C> {
+    fn input_str(&mut self, line: &str, output: &mut Output) {
+        match self.shell.context.try_borrow_mut() {
+            Ok(mut context) => self.state.input_str(self.shell.commands, &mut context, line, output),
+            Err(_) => { let _ = writeln!(output, "shell busy"); },
+        }
+    }
+
+    fn input_secret(&mut self, secret: &str, output: &mut Output) {
+        match self.shell.context.try_borrow_mut() {
+            Ok(mut context) => self.state.input_secret(&mut context, secret, output),
+            Err(_) => { let _ = writeln!(output, "shell busy"); },
+        }
+    }
+
+    fn poll(&mut self, output: &mut Output) {
+        match self.shell.context.try_borrow_mut() {
+            Ok(mut context) => self.state.poll(&mut context, output),
+            // try again on the next poll
+            Err(_) => output.keep_running(),
+        }
+    }
+
+    fn prompt(&mut self, prompt: &str, output: &mut Output) {
+        match self.shell.context.try_borrow() {
+            Ok(context) => self.state.prompt(&context, prompt, output),
+            Err(_) => output.write_bytes(prompt.as_bytes()),
+        }
+    }
+
+    fn complete(&mut self, line: &str, candidate: &mut dyn FnMut(&'static str)) {
+        if let Ok(context) = self.shell.context.try_borrow() {
+            complete(self.shell.commands, &context, line, candidate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::OutputNewline;
+
+    fn words(line: &str) -> Result<Vec<&str>, CommandError> {
+        let mut args = Args::new(line);
+        let mut words = Vec::new();
+        while let Some(word) = args.next_str()? {
+            words.push(word);
+        }
+        Ok(words)
+    }
+
+    fn run_help(commands: &[Command<()>], line: &str) -> (Result<(), CommandError>, String) {
+        let mut written = Vec::new();
+        let mut sink = |c| written.push(c);
+        let result = {
+            let mut output = Output::with_newline(&mut sink, OutputNewline::Lf);
+            let mut args = Args::new(line);
+            args.next_str().unwrap();
+            help(commands, &mut args, &mut output)
+        };
+        (result, String::from_utf8(written).unwrap())
+    }
+
+    fn nop(_: &mut (), _: &mut Args, _: &mut Output) -> Result<(), CommandError> {
+        Ok(())
+    }
+
+    #[test]
+    fn splits_on_white_space() {
+        assert_eq!(words("  md\t0x100   4 "), Ok(vec!["md", "0x100", "4"]));
+        assert_eq!(words("   "), Ok(vec![]));
+    }
+
+    #[test]
+    fn quotes_keep_white_space() {
+        assert_eq!(words("echo \"a  b\" 'c d' \"\""), Ok(vec!["echo", "a  b", "c d", ""]));
+        // the other quote and a backslash are taken as they are
+        assert_eq!(words("echo 'say \"hi\"' \"it's\" a\\ b"), Ok(vec!["echo", "say \"hi\"", "it's", "a\\", "b"]));
+        assert_eq!(words("echo \"a b"), Err(CommandError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(u32::from_arg("0x1F"), Some(0x1f));
+        assert_eq!(u32::from_arg("0XFFFFFFFF"), Some(u32::MAX));
+        assert_eq!(u8::from_arg("0b1010"), Some(10));
+        assert_eq!(u8::from_arg("256"), None);
+        assert_eq!(u8::from_arg("-1"), None);
+        assert_eq!(i32::from_arg("-42"), Some(-42));
+        assert_eq!(i32::from_arg("-0x10"), Some(-16));
+        assert_eq!(i8::from_arg("-0b10000000"), Some(i8::MIN));
+        assert_eq!(i8::from_arg("128"), None);
+        assert_eq!(i32::from_arg("--1"), None);
+        assert_eq!(i32::from_arg("-+1"), None);
+        assert_eq!(u32::from_arg("0x"), None);
+        assert_eq!(bool::from_arg("on"), Some(true));
+        assert_eq!(bool::from_arg("2"), None);
+    }
+
+    #[test]
+    fn missing_invalid_and_extra_arguments() {
+        let mut args = Args::new("mw 0x100");
+        assert_eq!(args.next_str(), Ok(Some("mw")));
+        assert_eq!(args.required::<u32>(), Ok(0x100));
+        assert_eq!(args.required::<u32>(), Err(CommandError::MissingArgument(2)));
+        assert_eq!(args.optional::<u32>(), Ok(None));
+        assert_eq!(args.finish(), Ok(()));
+
+        let mut args = Args::new("mw zz");
+        args.next_str().unwrap();
+        assert_eq!(args.required::<u32>(), Err(CommandError::InvalidArgument(1)));
+
+        let mut args = Args::new("led on now");
+        args.next_str().unwrap();
+        assert_eq!(args.required::<bool>(), Ok(true));
+        assert_eq!(args.rest(), "now");
+        assert_eq!(args.finish(), Err(CommandError::TooManyArguments));
+    }
+
+    #[test]
+    fn help_lines_up_the_names() {
+        let commands = [
+            Command::new("md", "md <address>  display memory", nop),
+            Command::new("regdump", "regdump  dump registers", nop),
+        ];
+        let (result, text) = run_help(&commands, "help");
+        assert_eq!(result, Ok(()));
+        assert_eq!(text, "\
+help     show this help
+md       md <address>  display memory
+regdump  regdump  dump registers
+");
+
+        let (result, text) = run_help(&commands[..1], "help");
+        assert_eq!(result, Ok(()));
+        assert_eq!(text, "help  show this help\nmd    md <address>  display memory\n");
+    }
+
+    #[test]
+    fn help_for_one_command() {
+        let commands = [Command::new("md", "md <address>  display memory", nop)];
+        assert_eq!(run_help(&commands, "help md"), (Ok(()), "md <address>  display memory\n".into()));
+        assert_eq!(run_help(&commands, "help help"), (Ok(()), "help [command]\n".into()));
+        assert_eq!(run_help(&commands, "help mw"), (Err(CommandError::UnknownCommand), String::new()));
+        assert_eq!(run_help(&commands, "help md md"), (Err(CommandError::TooManyArguments), String::new()));
+    }
+}
@@ -5,261 +5,1330 @@ use core::option::Option::*;
 use core::ops::FnMut;
 use core::str;
 use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use log::debug;
 
+use crate::escape::{Event, Key, Modifiers, Parser};
+use crate::history::History;
+use crate::transport::{Closures, Transport};
+use crate::unicode;
+
 const DEL:u8 = b'\x7f';
 const ESC:u8 = b'\x1b';
 const CR:u8  = b'\x0d';
 const LF:u8  = b'\x0a';
+const BEL:u8 = b'\x07';
+const NUL:u8 = b'\x00';
+const TAB:u8 = b'\x09';
+const CTRL_A:u8 = b'\x01';
+const CTRL_B:u8 = b'\x02';
+const CTRL_C:u8 = b'\x03';
+const CTRL_D:u8 = b'\x04';
+const CTRL_E:u8 = b'\x05';
+const CTRL_F:u8 = b'\x06';
+const CTRL_H:u8 = b'\x08';
+const CTRL_K:u8 = b'\x0b';
+const CTRL_L:u8 = b'\x0c';
+const CTRL_N:u8 = b'\x0e';
+const CTRL_P:u8 = b'\x10';
+const XON:u8 = b'\x11';
+const XOFF:u8 = b'\x13';
+const CTRL_U:u8 = b'\x15';
+const CTRL_W:u8 = b'\x17';
+
+const TX_BUFFER_SIZE:usize = 64;
+// keys received while the output waits for XON
+const RX_STASH_SIZE:usize = 16;
 
-const CURSOL_NEXT:[u8;4] = [ESC, b'[', b'1', b'C'];
-const CURSOL_PREV:[u8;4] = [ESC, b'[', b'1', b'D'];
+const ERASE_RIGHT_SIDE_OF_CURSOR:[u8;3] = [ESC, b'[', b'K'];
+const ERASE_BELOW_CURSOR:[u8;3] = [ESC, b'[', b'J'];
+const CLEAR_SCREEN:[u8;7] = [ESC, b'[', b'H', ESC, b'[', b'2', b'J'];
+const MORE:&[u8] = b"--More--";
+// save the cursor, move to the far corner, report the position, restore
+const QUERY_SIZE:&[u8] = b"\x1b7\x1b[999;999H\x1b[6n\x1b8";
+// smallest reply to QUERY_SIZE taken as the size, xterm sends F3 with
+// modifiers as ESC [ 1 ; m R
+const MIN_ROWS:u16 = 2;
+const MIN_COLUMNS:u16 = 10;
 #[cfg(not(all()))]
 const DEL_CHARS_LEFT_SIDE_OF_CURSOR:[u8;4] = [ ESC, b'[', b'1', b'K' ];
 
-enum InputMode {
-    Normal, Esc, CsiFirst
+// Volatile so that clearing a secret is not optimized away.
+pub(crate) fn zeroize(bytes: &mut [u8]) {
+    for c in bytes.iter_mut() {
+        unsafe { core::ptr::write_volatile(c, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+pub trait InputHandler {
+    fn input_str(&mut self, line: &str, output: &mut Output);
+
+    // Called on Tab with the line up to the cursor. Every word which may
+    // follow is given to `candidate`, the console picks the ones matching
+    // the word under the cursor.
+    fn complete(&mut self, _line: &str, _candidate: &mut dyn FnMut(&'static str)) {}
+
+    // Receives the line read after a read_secret request. The console
+    // clears its copy of the secret as soon as this returns.
+    fn input_secret(&mut self, _secret: &str, _output: &mut Output) {}
+
+    // Called when a keystroke is dropped because the line buffer is full,
+    // the console has already rung the bell.
+    fn overflow(&mut self) {}
+
+    // Writes the prompt each time it is shown, `prompt` is the one set on
+    // the console. Override it to show state or colors.
+    fn prompt(&mut self, prompt: &str, output: &mut Output) {
+        output.write_bytes(prompt.as_bytes());
+    }
+
+    // Called on every Console::input while the command started by
+    // input_str asks to keep running, see Output::keep_running. After
+    // Ctrl-C it is called once more with Output::cancelled set and the
+    // command is over whatever it answers.
+    fn poll(&mut self, _output: &mut Output) {}
+
+    // Ctrl-D on an empty line.
+    fn end_of_input(&mut self, _output: &mut Output) {}
+}
+
+// Set by Ctrl-C. A command which blocks the console can't see the
+// keystroke, an interrupt handler watching for 0x03 may set it instead.
+pub struct CancelToken(AtomicBool);
+
+impl CancelToken {
+    pub const fn new() -> Self {
+        CancelToken(AtomicBool::new(false))
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretMode {
+    // every character is echoed as '*'
+    Masked,
+    // nothing is echoed
+    Hidden,
+}
+
+// Bytes which submit the line. The second byte of a CR LF or LF CR pair
+// and the NUL of a CR NUL (telnet) are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputNewline {
+    Cr,
+    Lf,
+    Any,
+}
+
+// Sequence sent to start a new line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputNewline {
+    Cr,
+    Lf,
+    CrLf,
+    LfCr,
+}
+
+impl OutputNewline {
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            OutputNewline::Cr => &[CR],
+            OutputNewline::Lf => &[LF],
+            OutputNewline::CrLf => &[CR, LF],
+            OutputNewline::LfCr => &[LF, CR],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl<F> InputHandler for F
+where F: FnMut(&str)
+{
+    fn input_str(&mut self, line: &str, _output: &mut Output) {
+        (self)(line)
+    }
+}
+
+pub trait Sink {
+    fn put(&mut self, bytes: &[u8]);
+
+    // Waits for a key, None if the sink can't read any. Used by the pager.
+    fn wait_key(&mut self) -> Option<u8> {
+        None
+    }
+
+    // The terminal has sent XOFF, see Console::set_flow_control.
+    fn paused(&self) -> bool {
+        false
+    }
+
+    // Output has been lost, the writes of the handler return fmt::Error.
+    fn failed(&self) -> bool {
+        false
+    }
+}
+
+impl<F> Sink for F
+where F: FnMut(u8)
+{
+    fn put(&mut self, bytes: &[u8]) {
+        for &c in bytes {
+            (self)(c);
+        }
+    }
+}
+
+// Output given to an InputHandler while it processes a line.
+// A lone LF is sent as the newline of the console, LF CR by default.
+pub struct Output<'a> {
+    sink: &'a mut dyn Sink,
+    newline: OutputNewline,
+    last: u8,
+    secret: Option<(SecretMode, &'static str)>,
+    running: bool,
+    cancelled: bool,
+    cancel: Option<&'static CancelToken>,
+    // rows of the pager and lines written since the last page break
+    page: Option<usize>,
+    lines: usize,
+    // the operator has quit the pager
+    discard: bool,
 }
 
-pub struct Console<Getc, Putc, InputStr>
+impl<'a> Output<'a> {
+    pub fn new(sink: &'a mut dyn Sink) -> Self {
+        Self::with_newline(sink, OutputNewline::LfCr)
+    }
+
+    pub fn with_newline(sink: &'a mut dyn Sink, newline: OutputNewline) -> Self {
+        Output {
+            sink,
+            newline,
+            last: 0,
+            secret: None,
+            running: false,
+            cancelled: false,
+            cancel: None,
+            page: None,
+            lines: 0,
+            discard: false,
+        }
+    }
+
+    // Keeps the command running after input_str or poll returns, the
+    // console polls it instead of showing the prompt.
+    pub fn keep_running(&mut self) {
+        self.running = true;
+    }
+
+    fn with_cancel(mut self, cancelled: bool, cancel: Option<&'static CancelToken>) -> Self {
+        self.cancelled = cancelled;
+        self.cancel = cancel;
+        self
+    }
+
+    fn with_pager(mut self, page: Option<usize>, lines: usize) -> Self {
+        self.page = page;
+        self.lines = lines;
+        self
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancelled || self.cancel.is_some_and(|cancel| cancel.is_cancelled())
+    }
+
+    // Reads the next line as a secret, it is passed to input_secret
+    // instead of input_str and `prompt` is shown instead of the usual one.
+    pub fn read_secret(&mut self, mode: SecretMode, prompt: &'static str) {
+        self.secret = Some((mode, prompt));
+    }
+
+    pub fn secret_requested(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    // The terminal has asked to stop the output with XOFF. A command that
+    // keeps running may wait for the next poll instead of blocking in the
+    // next write.
+    pub fn paused(&self) -> bool {
+        self.sink.paused()
+    }
+
+    // Quitting drops the rest of the output and ends the command.
+    fn more(&mut self) {
+        self.sink.put(MORE);
+        let key = self.sink.wait_key();
+        self.sink.put(b"\r");
+        self.sink.put(&ERASE_RIGHT_SIDE_OF_CURSOR);
+        match key {
+            Some(CR) | Some(LF) => self.lines -= 1,
+            Some(b'q') | Some(b'Q') | Some(CTRL_C) => {
+                self.discard = true;
+                self.cancelled = true;
+            },
+            _ => self.lines = 0,
+        }
+    }
+
+    // Sets the foreground color with SGR, None goes back to the default.
+    pub fn set_color(&mut self, color: Option<Color>) {
+        match color {
+            Some(color) => self.sink.put(&[ESC, b'[', b'3', b'0' + color as u8, b'm']),
+            None => self.sink.put(&[ESC, b'[', b'0', b'm']),
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.discard { return; }
+        let mut start = 0;
+        for (p, &c) in bytes.iter().enumerate() {
+            let last = core::mem::replace(&mut self.last, c);
+            if c != LF { continue; }
+            if last != CR && self.newline != OutputNewline::Lf {
+                self.sink.put(&bytes[start..p]);
+                self.sink.put(self.newline.as_bytes());
+            }
+            else {
+                self.sink.put(&bytes[start..=p]);
+            }
+            start = p + 1;
+            if let Some(rows) = self.page {
+                self.lines += 1;
+                if self.lines + 1 >= rows {
+                    self.more();
+                    if self.discard { return; }
+                }
+            }
+        }
+        self.sink.put(&bytes[start..]);
+    }
+}
+
+impl Write for Output<'_> {
+    fn write_str(&mut self, s: &str) -> Result<(),core::fmt::Error> {
+        self.write_bytes(s.as_bytes());
+        if self.sink.failed() { Err(core::fmt::Error) } else { Ok(()) }
+    }
+}
+
+// Collects the echo and redraw sequences so the transport gets them in
+// a few writes instead of one call per byte. The first error is kept
+// until Console reports it.
+//
+// With flow control XOFF holds back every write until XON comes, the
+// keys received meanwhile are stashed for Console.
+struct Port<T: Transport> {
+    transport: T,
+    tx: [u8; TX_BUFFER_SIZE],
+    tx_len: usize,
+    error: Option<T::Error>,
+    flow_control: bool,
+    paused: bool,
+    rx: [u8; RX_STASH_SIZE],
+    rx_len: usize,
+}
+
+impl<T: Transport> Port<T> {
+    fn new(transport: T) -> Self {
+        Port {
+            transport,
+            tx: [0; TX_BUFFER_SIZE],
+            tx_len: 0,
+            error: None,
+            flow_control: false,
+            paused: false,
+            rx: [0; RX_STASH_SIZE],
+            rx_len: 0,
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        match self.transport.read() {
+            Ok(Some(XOFF)) if self.flow_control => {
+                self.paused = true;
+                None
+            },
+            Ok(Some(XON)) if self.flow_control => {
+                self.paused = false;
+                None
+            },
+            Ok(c) => c,
+            Err(e) => {
+                self.error.get_or_insert(e);
+                None
+            }
+        }
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        if self.rx_len > 0 {
+            let c = self.rx[0];
+            self.rx.copy_within(1..self.rx_len, 0);
+            self.rx_len -= 1;
+            return Some(c);
+        }
+        self.receive()
+    }
+
+    fn stash(&mut self, c: u8) {
+        // dropped like an overrun if the stash is full
+        if self.rx_len < self.rx.len() {
+            self.rx[self.rx_len] = c;
+            self.rx_len += 1;
+        }
+    }
+
+    // A command printing does not read the input, so the XOFF sent while
+    // it does is looked for before each chunk.
+    fn check_flow(&mut self) {
+        if self.flow_control {
+            if let Some(c) = self.receive() {
+                self.stash(c);
+            }
+        }
+    }
+
+    fn wait_for_xon(&mut self) {
+        while self.paused && self.error.is_none() {
+            if let Some(c) = self.receive() {
+                self.stash(c);
+            }
+        }
+    }
+
+    fn write(&mut self, len: usize) {
+        self.check_flow();
+        self.wait_for_xon();
+        if let Err(e) = self.transport.write(&self.tx[..len]) {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.tx_len == 0 { return; }
+        self.check_flow();
+        self.wait_for_xon();
+        let result = self.transport.write(&self.tx[..self.tx_len]).and_then(|_| self.transport.flush());
+        if let Err(e) = result {
+            self.error.get_or_insert(e);
+        }
+        self.tx_len = 0;
+    }
+
+    fn result(&mut self) -> Result<(), T::Error> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+}
+
+impl<T: Transport> Sink for Port<T> {
+    fn put(&mut self, bytes: &[u8]) {
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            if self.tx_len == self.tx.len() {
+                self.write(self.tx_len);
+                self.tx_len = 0;
+            }
+            let n = bytes.len().min(self.tx.len() - self.tx_len);
+            self.tx[self.tx_len..self.tx_len + n].copy_from_slice(&bytes[..n]);
+            self.tx_len += n;
+            bytes = &bytes[n..];
+        }
+    }
+
+    fn wait_key(&mut self) -> Option<u8> {
+        self.flush();
+        loop {
+            if let Some(c) = self.read() {
+                return Some(c);
+            }
+            if self.error.is_some() {
+                return None;
+            }
+        }
+    }
+
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+// Passes the prompt through and keeps the column it ends on, escape
+// sequences such as colors take no room.
+struct Measure<'a> {
+    sink: &'a mut dyn Sink,
+    column: usize,
+    // 1 after ESC, 2 inside ESC [
+    escape: u8,
+    utf8_pending: [u8; 4],
+    utf8_len: usize,
+}
+
+impl<'a> Measure<'a> {
+    fn new(sink: &'a mut dyn Sink) -> Self {
+        Measure { sink, column: 0, escape: 0, utf8_pending: [0; 4], utf8_len: 0 }
+    }
+
+    fn measure(&mut self, c: u8) {
+        match (self.escape, c) {
+            (1, b'[') => self.escape = 2,
+            (1, _) => self.escape = 0,
+            (2, 0x40..=0x7e) => self.escape = 0,
+            (2, _) => (),
+            (_, ESC) => self.escape = 1,
+            (_, CR | LF) => self.column = 0,
+            (_, 0x20..=0x7e) => self.column += 1,
+            (_, 0x80..=0xff) => {
+                if !unicode::is_continuation(c) {
+                    self.utf8_len = 0;
+                }
+                if self.utf8_len < self.utf8_pending.len() {
+                    self.utf8_pending[self.utf8_len] = c;
+                    self.utf8_len += 1;
+                }
+                if unicode::sequence_len(self.utf8_pending[0]) == self.utf8_len {
+                    self.column += unicode::str_width(&self.utf8_pending[..self.utf8_len]);
+                    self.utf8_len = 0;
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+impl Sink for Measure<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.sink.put(bytes);
+        for &c in bytes {
+            self.measure(c);
+        }
+    }
+
+    fn wait_key(&mut self) -> Option<u8> {
+        self.sink.wait_key()
+    }
+
+    fn paused(&self) -> bool {
+        self.sink.paused()
+    }
+
+    fn failed(&self) -> bool {
+        self.sink.failed()
+    }
+}
+
+pub struct Console<T, InputStr>
 where
-    Getc: FnMut() -> Option<u8>,
-    Putc: FnMut(u8),
-    InputStr: FnMut(&str)
+    T: Transport,
+    InputStr: InputHandler
 {
     cursor_pos: usize,
     tail_pos: usize,
-    input_mode: InputMode,
-    csi_pn: usize,
+    parser: Parser,
+    utf8_pending: [u8; 4],
+    utf8_len: usize,
     buffer: &'static mut [u8],
     prompt: &'static str,
-    getc: Getc,
-    putc: Putc,
+    secret: Option<(SecretMode, &'static str)>,
+    input_newline: InputNewline,
+    output_newline: OutputNewline,
+    // newline byte just received, the other half of a pair is dropped
+    newline_pending: u8,
+    // a command is running, see Output::keep_running
+    running: bool,
+    cancelled: bool,
+    cancel: Option<&'static CancelToken>,
+    // pager rows and lines shown since the last page break
+    page: Option<usize>,
+    page_lines: usize,
+    // rows and columns reported by the terminal, see QUERY_SIZE
+    size: Option<(usize, usize)>,
+    // ask again on the first key, the terminal may have been attached
+    // after startup
+    size_requery: bool,
+    // column the prompt ends on, the line is edited from there
+    prompt_width: usize,
+    history: Option<History>,
+    port: Port<T>,
     input_str: Option<InputStr>
 }
 
-impl<Getc, Putc, InputStr> Write for Console<Getc, Putc, InputStr>
-where Getc: FnMut() -> Option<u8>,
-      Putc: FnMut(u8),
-      InputStr: FnMut(&str)
+impl<T, InputStr> Write for Console<T, InputStr>
+where T: Transport,
+      InputStr: InputHandler
 {
     fn write_str(&mut self, s: &str) -> Result<(),core::fmt::Error> {
-        for c in s.chars() {
-            ((*self).putc)(c as u8);
-        }
-        Ok(())
+        self.port.put(s.as_bytes());
+        self.port.flush();
+        if self.port.error.is_some() { Err(core::fmt::Error) } else { Ok(()) }
     }
 }
 
-impl<Getc,Putc,InputStr> Console<Getc,Putc,InputStr>
+impl<Getc,Putc,InputStr> Console<Closures<Getc,Putc>,InputStr>
 where Getc: FnMut() -> Option<u8>,
       Putc: FnMut(u8),
-      InputStr: FnMut(&str)
+      InputStr: InputHandler
 {
-    fn move_cursor_prev(&mut self) {
-        for c in CURSOL_PREV {
-            ((*self).putc)(c);
-        }
+    /// # Safety
+    ///
+    /// `buffer` is used as the line buffer for the whole lifetime of the console
+    /// and must not be accessed by anyone else.
+    pub unsafe fn new(buffer: &'static mut [u8],
+                      prompt: &'static str,
+                      getc : Getc,
+                      putc : Putc,
+                      input_str: Option<InputStr>) -> Self {
+        Self::with_transport(buffer, prompt, Closures::new(getc, putc), input_str)
+    }
+}
+
+impl<T,InputStr> Console<T,InputStr>
+where T: Transport,
+      InputStr: InputHandler
+{
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        self.port.put(bytes);
     }
 
-    fn move_cursor_next(&mut self){
-        for c in CURSOL_NEXT {
-            ((*self).putc)(c);
+    // ESC [ n <f>
+    fn put_csi(&mut self, n: usize, f: u8) {
+        let mut seq = [0u8; 24];
+        let mut i = seq.len() - 1;
+        let mut n = n;
+        seq[i] = f;
+        loop {
+            i -= 1;
+            seq[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 { break; }
         }
+        seq[i-2] = ESC;
+        seq[i-1] = b'[';
+        self.put_bytes(&seq[i-2..]);
+    }
+
+    fn cursor_left(&mut self, columns: usize) {
+        if columns > 0 { self.put_csi(columns, b'D'); }
+    }
+
+    fn cursor_right(&mut self, columns: usize) {
+        if columns > 0 { self.put_csi(columns, b'C'); }
     }
 
     #[cfg(not(all()))]
     fn del_chars_left_side_of_cursor(&mut self) {
-        for c in DEL_CHARS_LEFT_SIDE_OF_CURSOR {
-            ((*self).putc)(c);
+        self.port.put(&DEL_CHARS_LEFT_SIDE_OF_CURSOR);
+    }
+
+    fn erase_below_cursor(&mut self) {
+        self.port.put(&ERASE_BELOW_CURSOR);
+    }
+
+    fn width(&self, from: usize, to: usize) -> usize {
+        match self.secret {
+            None => unicode::str_width(&self.buffer[from..to]),
+            Some((SecretMode::Masked, _)) => self.buffer[from..to].iter().filter(|c| !unicode::is_continuation(**c)).count(),
+            Some((SecretMode::Hidden, _)) => 0,
         }
     }
 
-    fn input_normal(&mut self, c:u8){
-        if c.is_ascii_control() {
-            match c {
-                CR => {
-                    //debug!("input CR");
-                    if let Some(ref mut input_str) = (*self).input_str {
-                        let mut len = 0;
-                        for b in &mut (*self).buffer.iter() {
-                            if *b == 0 { break; };
-                            len += 1;
-                        }
-                        if let Ok(command) = str::from_utf8(&(*self).buffer[..(len+1)]) {
-                            (input_str)(command);
-                        }
-                    }
-                    ((*self).putc)(LF);
-                    ((*self).putc)(CR);
-                    (*self).cursor_pos = 0;
-                    (*self).tail_pos = 0;
-                    (*self).buffer.fill(0);
-                    for c in (*self).prompt.chars() {
-                        ((*self).putc)(c as u8)
-                    }
-                },
-                DEL => {
-                    // Back Space
-                    if (*self).tail_pos > 0 {
-                        if (*self).cursor_pos < (*self).tail_pos {
-                            if (*self).cursor_pos > 0 {
-                                for p in (*self).cursor_pos..(*self).tail_pos {
-                                    (*self).buffer[p-1] = (*self).buffer[p];
-                                }
-                                (*self).buffer[(*self).tail_pos-1] = 0;
-                                (*self).move_cursor_prev();
-                                (*self).cursor_pos -= 1;
-                                (*self).tail_pos -= 1;
-                                for p in (*self).cursor_pos..(*self).tail_pos {
-                                    ((*self).putc)((*self).buffer[p]);
-                                }
-                                ((*self).putc)(b' ');
-                                for _ in (*self).cursor_pos..=(*self).tail_pos {
-                                    (*self).move_cursor_prev();
-                                }
-                            }
-                        }
-                        else {
-                            (*self).move_cursor_prev();
-                            ((*self).putc)(b' ');
-                            (*self).move_cursor_prev();
-                            (*self).buffer[(*self).tail_pos] = 0;
-                            (*self).cursor_pos -= 1;
-                            (*self).tail_pos -= 1;
-                        }
-                    }
-                },
-                ESC => {
-                    debug!("input ESC");
-                    (*self).input_mode = InputMode::Esc
-                },
-                _ => ()
+    // Echoes buffer[from..to], a secret is masked or hidden.
+    fn put_line(&mut self, from: usize, to: usize) {
+        match self.secret {
+            None => self.port.put(&self.buffer[from..to]),
+            Some((SecretMode::Masked, _)) => {
+                for _ in 0..self.width(from, to) {
+                    self.port.put(b"*");
+                }
+            },
+            Some((SecretMode::Hidden, _)) => (),
+        }
+    }
+
+    fn put_newline(&mut self) {
+        self.port.put(self.output_newline.as_bytes());
+    }
+
+    fn put_prompt(&mut self) {
+        let mut measure = Measure::new(&mut self.port);
+        match (self.secret, self.input_str.as_mut()) {
+            (Some((_, prompt)), _) => measure.put(prompt.as_bytes()),
+            (None, Some(input_str)) => {
+                input_str.prompt(self.prompt, &mut Output::with_newline(&mut measure, self.output_newline));
+            },
+            (None, None) => measure.put(self.prompt.as_bytes()),
+        }
+        self.prompt_width = measure.column;
+        self.wrap(0, self.prompt_width);
+    }
+
+    // Without the size the line is taken not to wrap, the cursor is only
+    // moved left and right from where it is.
+    fn columns(&self) -> usize {
+        self.size.map_or(usize::MAX, |(_, columns)| columns)
+    }
+
+    fn query_size(&mut self) {
+        if self.size.is_none() {
+            self.put_bytes(QUERY_SIZE);
+        }
+    }
+
+    // Column buffer[..pos] ends on, counted from the start of the prompt
+    // row and running on across the rows the line wraps to.
+    fn column_of(&self, pos: usize) -> usize {
+        let columns = self.columns();
+        let mut column = self.prompt_width;
+        let mut p = 0;
+        while p < pos {
+            let next = unicode::next_boundary(&self.buffer[..pos], p);
+            let width = match self.secret {
+                None => unicode::str_width(&self.buffer[p..next]),
+                Some((SecretMode::Masked, _)) => 1,
+                Some((SecretMode::Hidden, _)) => 0,
+            };
+            // a wide character does not fit on the last column
+            if column % columns + width > columns {
+                column += columns - column % columns;
             }
+            column += width;
+            p = next;
+        }
+        column
+    }
+
+    // Having printed up to the last column the terminal keeps the cursor
+    // there until the next character comes, take it to the next row so
+    // that it is where column_of says.
+    fn wrap(&mut self, start: usize, end: usize) {
+        if end > start && end.is_multiple_of(self.columns()) {
+            self.put_bytes(b"\n\r");
+        }
+    }
+
+    fn move_cursor(&mut self, from: usize, to: usize) {
+        let columns = self.columns();
+        let (from_row, to_row) = (from / columns, to / columns);
+        if to_row < from_row {
+            self.put_csi(from_row - to_row, b'A');
+        }
+        else if to_row > from_row {
+            self.put_csi(to_row - from_row, b'B');
+        }
+        let (from_column, to_column) = (from % columns, to % columns);
+        if to_column < from_column {
+            self.cursor_left(from_column - to_column);
         }
         else {
-            if (*self).cursor_pos < (*self).tail_pos {
-                for p in ((*self).cursor_pos..=(*self).tail_pos).rev() {
-                    (*self).buffer[p] = (*self).buffer[p-1];
-                }
-                (*self).buffer[(*self).cursor_pos] = c;
+            self.cursor_right(to_column - from_column);
+        }
+    }
+
+    // Echoes buffer[from..tail_pos] with the cursor at `from`, then puts
+    // the cursor on cursor_pos. `erase` clears what is left of a longer
+    // line. Text running on to the next row is always erased first, the
+    // terminal leaves the last column alone when it wraps a wide character.
+    fn put_tail(&mut self, from: usize, erase: bool) {
+        let columns = self.columns();
+        let start = self.column_of(from);
+        let end = self.column_of(self.tail_pos);
+        if erase || end / columns > start / columns {
+            self.erase_below_cursor();
+        }
+        self.put_line(from, self.tail_pos);
+        self.wrap(start, end);
+        self.move_cursor(end, self.column_of(self.cursor_pos));
+    }
+
+    // Moves to the first column of the row the prompt is on.
+    fn move_to_line_start(&mut self) {
+        if !self.running {
+            let rows = self.column_of(self.cursor_pos) / self.columns();
+            if rows > 0 {
+                self.put_csi(rows, b'A');
+            }
+        }
+        self.put_bytes(b"\r");
+    }
+
+    // Leaves the line for the output below it.
+    fn leave_line(&mut self) {
+        self.move_end();
+        let end = self.column_of(self.tail_pos);
+        if end == 0 || !end.is_multiple_of(self.columns()) {
+            self.put_newline();
+        }
+    }
+
+    fn clear_buffer(&mut self) {
+        zeroize(self.buffer);
+        // a character cut short may be part of a secret too
+        zeroize(&mut self.utf8_pending);
+        self.utf8_len = 0;
+        self.cursor_pos = 0;
+        self.tail_pos = 0;
+    }
+
+    fn replace_line(&mut self, line: &[u8]) {
+        self.move_home();
+        let len = unicode::floor_boundary(line, self.buffer.len().min(line.len()));
+        self.buffer.fill(0);
+        self.buffer[..len].copy_from_slice(&line[..len]);
+        self.cursor_pos = len;
+        self.tail_pos = len;
+        self.put_tail(0, true);
+    }
+
+    fn history_prev(&mut self) {
+        if self.secret.is_some() { return; }
+        if let Some(mut history) = self.history.take() {
+            if let Some(line) = history.older(&self.buffer[..self.tail_pos]) {
+                self.replace_line(line);
+            }
+            self.history = Some(history);
+        }
+    }
+
+    fn history_next(&mut self) {
+        if self.secret.is_some() { return; }
+        if let Some(mut history) = self.history.take() {
+            if let Some(line) = history.newer() {
+                self.replace_line(line);
+            }
+            self.history = Some(history);
+        }
+    }
+
+    fn redraw_line(&mut self) {
+        if self.running { return; }
+        self.put_prompt();
+        self.put_tail(0, false);
+    }
 
-                for p in (*self).cursor_pos..=(*self).tail_pos {
-                    ((*self).putc)((*self).buffer[p]);
+    fn complete(&mut self) {
+        if self.secret.is_some() { return; }
+        let input_str = match self.input_str {
+            Some(ref mut input_str) => input_str,
+            None => return
+        };
+        let line = match str::from_utf8(&self.buffer[..self.cursor_pos]) {
+            Ok(line) => line,
+            Err(_) => return
+        };
+        let word = &line[line.rfind(' ').map_or(0, |p| p + 1)..];
+
+        let mut first: Option<&'static str> = None;
+        let mut common = 0;
+        let mut count = 0;
+        input_str.complete(line, &mut |candidate| {
+            if !candidate.starts_with(word) { return; }
+            match first {
+                None => { first = Some(candidate); common = candidate.len(); },
+                Some(first) => {
+                    common = first.bytes().zip(candidate.bytes())
+                        .take(common)
+                        .take_while(|(a, b)| a == b)
+                        .count();
                 }
+            }
+            count += 1;
+        });
+
+        let first = match first {
+            Some(first) => first,
+            None => return
+        };
+        while !first.is_char_boundary(common) { common -= 1; }
+        let word_len = word.len();
+        if !self.insert_bytes(&first.as_bytes()[word_len..common]) { return; }
+        if count == 1 {
+            self.insert_bytes(b" ");
+        }
+        else if common == word_len {
+            self.leave_line();
+            if let (Some(input_str), Ok(line)) = (self.input_str.as_mut(), str::from_utf8(&self.buffer[..self.cursor_pos])) {
+                let word = &line[line.rfind(' ').map_or(0, |p| p + 1)..];
+                let port = &mut self.port;
+                input_str.complete(line, &mut |candidate| {
+                    if !candidate.starts_with(word) { return; }
+                    port.put(candidate.as_bytes());
+                    port.put(b"  ");
+                });
+            }
+            self.put_newline();
+            self.redraw_line();
+        }
+    }
+
+    fn move_to(&mut self, pos: usize) {
+        self.move_cursor(self.column_of(self.cursor_pos), self.column_of(pos));
+        self.cursor_pos = pos;
+    }
+
+    fn move_home(&mut self) {
+        self.move_to(0);
+    }
+
+    fn move_end(&mut self) {
+        self.move_to(self.tail_pos);
+    }
+
+    fn prev_char_pos(&self) -> usize {
+        unicode::prev_boundary(&self.buffer[..self.tail_pos], self.cursor_pos)
+    }
+
+    fn next_char_pos(&self) -> usize {
+        unicode::next_boundary(&self.buffer[..self.tail_pos], self.cursor_pos)
+    }
+
+    // A secret is one word, its spaces must not show through cursor moves.
+    fn prev_word_pos(&self) -> usize {
+        if self.secret.is_some() { return 0; }
+        let mut p = self.cursor_pos;
+        while p > 0 && self.buffer[p-1] == b' ' { p -= 1; }
+        while p > 0 && self.buffer[p-1] != b' ' { p -= 1; }
+        p
+    }
 
-                for _ in (*self).cursor_pos..(*self).tail_pos {
-                    (*self).move_cursor_prev();
+    fn next_word_pos(&self) -> usize {
+        if self.secret.is_some() { return self.tail_pos; }
+        let mut p = self.cursor_pos;
+        while p < self.tail_pos && self.buffer[p] == b' ' { p += 1; }
+        while p < self.tail_pos && self.buffer[p] != b' ' { p += 1; }
+        p
+    }
+
+    // Inserts at the cursor, returns false and rings the bell if the buffer
+    // has no room for it.
+    fn insert_bytes(&mut self, bytes: &[u8]) -> bool {
+        let n = bytes.len();
+        if n > self.buffer.len() - self.tail_pos {
+            self.put_bytes(&[BEL]);
+            if let Some(ref mut input_str) = self.input_str {
+                input_str.overflow();
+            }
+            return false;
+        }
+        let cursor_pos = self.cursor_pos;
+        self.buffer.copy_within(cursor_pos..self.tail_pos, cursor_pos + n);
+        self.buffer[cursor_pos..cursor_pos + n].copy_from_slice(bytes);
+        self.tail_pos += n;
+        self.cursor_pos += n;
+        self.put_tail(cursor_pos, false);
+        true
+    }
+
+    // Removes buffer[from..to] and leaves the cursor at `from`.
+    fn delete_range(&mut self, from: usize, to: usize) {
+        if from >= to || to > self.tail_pos { return; }
+        self.move_to(from);
+        let n = to - from;
+        self.buffer.copy_within(to..self.tail_pos, from);
+        self.buffer[self.tail_pos-n..self.tail_pos].fill(0);
+        self.tail_pos -= n;
+        self.put_tail(from, true);
+    }
+
+    fn enter(&mut self) {
+        //debug!("input CR");
+        self.leave_line();
+        let secret = self.secret.take();
+        let mut next_secret = None;
+        self.cancelled = false;
+        if let Some(cancel) = self.cancel {
+            cancel.reset();
+        }
+        if let Some(ref mut input_str) = self.input_str {
+            if let Ok(line) = str::from_utf8(&self.buffer[..self.tail_pos]) {
+                let mut output = Output::with_newline(&mut self.port, self.output_newline)
+                    .with_cancel(self.cancelled, self.cancel)
+                    .with_pager(self.page, 0);
+                if secret.is_some() {
+                    input_str.input_secret(line, &mut output);
                 }
-                (*self).tail_pos += 1;
-                (*self).cursor_pos += 1;
+                else {
+                    input_str.input_str(line, &mut output);
+                }
+                next_secret = output.secret;
+                self.running = output.running && !output.discard;
+                self.page_lines = output.lines;
             }
-            else {
-                (*self).buffer[(*self).cursor_pos] = c;
-                (*self).cursor_pos += 1;
-                (*self).tail_pos += 1;
-                ((*self).putc)(c)
+        }
+        if secret.is_none() {
+            if let Some(ref mut history) = self.history {
+                history.push(&self.buffer[..self.tail_pos]);
             }
         }
+        self.clear_buffer();
+        self.secret = next_secret;
+        if !self.running {
+            self.put_prompt();
+        }
     }
 
-    fn input_esc(&mut self, c:u8){
-        match c {
-            b'[' => { (*self).input_mode = InputMode::CsiFirst; self.csi_pn = 0; },
-            _ => { debug!(" ESC unknown code {:02x}", c); (*self).input_mode = InputMode::Normal; }
+    // Ctrl-C, the line is thrown away.
+    fn cancel_line(&mut self) {
+        self.move_end();
+        self.put_bytes(b"^C");
+        self.put_newline();
+        self.clear_buffer();
+        self.secret = None;
+        self.newline_pending = NUL;
+        if let Some(ref mut history) = self.history {
+            history.reset_cursor();
+        }
+        self.put_prompt();
+    }
+
+    fn clear_screen(&mut self) {
+        self.query_size();
+        self.put_bytes(&CLEAR_SCREEN);
+        self.redraw_line();
+    }
+
+    // Drives the running command, only Ctrl-C is taken from the input.
+    fn poll(&mut self, c: Option<u8>) {
+        // the LF of a CR LF may have gone to the command, the next one
+        // after it is a newline of its own
+        if c.is_some() {
+            self.newline_pending = NUL;
+        }
+        if c == Some(CTRL_C) {
+            self.cancelled = true;
+            if let Some(cancel) = self.cancel {
+                cancel.cancel();
+            }
+        }
+        if let Some(ref mut input_str) = self.input_str {
+            let mut output = Output::with_newline(&mut self.port, self.output_newline)
+                .with_cancel(self.cancelled, self.cancel)
+                .with_pager(self.page, self.page_lines);
+            input_str.poll(&mut output);
+            // quitting the pager ends the command like Ctrl-C
+            self.running = output.running && !self.cancelled && !output.discard;
+            self.page_lines = output.lines;
+        }
+        else {
+            self.running = false;
+        }
+        if !self.running {
+            if self.cancelled {
+                self.put_bytes(b"^C");
+                self.put_newline();
+            }
+            self.put_prompt();
         }
     }
 
-    fn input_csi_first(&mut self, c:u8){
-        if c.is_ascii_digit() {
-            self.csi_pn = (c - b'0') as usize;
+    // Collects the bytes of a multi-byte character before inserting it.
+    fn input_utf8(&mut self, c:u8) {
+        if unicode::is_continuation(c) {
+            if self.utf8_len == 0 || self.utf8_len >= self.utf8_pending.len() {
+                self.utf8_len = 0;
+                return;
+            }
+            self.utf8_pending[self.utf8_len] = c;
+            self.utf8_len += 1;
         }
         else {
+            self.utf8_pending[0] = c;
+            self.utf8_len = 1;
+        }
+        let pending = self.utf8_pending;
+        let len = self.utf8_len;
+        if unicode::sequence_len(pending[0]) == len {
+            self.utf8_len = 0;
+            if str::from_utf8(&pending[..len]).is_ok() {
+                self.insert_bytes(&pending[..len]);
+            }
+        }
+        else if unicode::sequence_len(pending[0]) < len {
+            self.utf8_len = 0;
+        }
+    }
+
+    fn input_normal(&mut self, c:u8){
+        if c >= 0x80 {
+            self.input_utf8(c);
+            return;
+        }
+        self.utf8_len = 0;
+        let pending = core::mem::replace(&mut self.newline_pending, NUL);
+        if c.is_ascii_control() {
             match c {
-                b'D' => {
-                    if (*self).cursor_pos > 0 {
-                        (*self).move_cursor_prev();
-                        (*self).cursor_pos -= 1;
-                    };
-                    (*self).input_mode = InputMode::Normal;
-                },
-                b'C' => {
-                    if (*self).cursor_pos < (*self).tail_pos {
-                        (*self).move_cursor_next();
-                        (*self).cursor_pos += 1;
+                LF if pending == CR => (),
+                CR if pending == LF => (),
+                NUL if pending == CR => (),
+                CTRL_C => self.cancel_line(),
+                CTRL_D if self.tail_pos == 0 => {
+                    self.leave_line();
+                    if let Some(ref mut input_str) = self.input_str {
+                        input_str.end_of_input(&mut Output::with_newline(&mut self.port, self.output_newline));
                     }
-                    (*self).input_mode = InputMode::Normal;
+                    self.put_prompt();
                 },
-                b'~' => {
-                    // DEL
-                    if (*self).cursor_pos < (*self).tail_pos {
-                        for p in (*self).cursor_pos..(*self).tail_pos {
-                            if p == (*self).buffer.len()-1 {
-                                (*self).buffer[p] = 0;
-                            }
-                            else {
-                                (*self).buffer[p] = (*self).buffer[p+1];
-                            }
-                        }
-
-                        (*self).tail_pos -= 1;
-                        for p in (*self).cursor_pos..(*self).tail_pos {
-                            ((*self).putc)((*self).buffer[p]);
-                        }
-                        ((*self).putc)(b' ');
-                        for _ in (*self).cursor_pos..=(*self).tail_pos {
-                            (*self).move_cursor_prev();
-                        }
-                    }
-                    (*self).input_mode = InputMode::Normal;
+                CTRL_D => self.delete_range(self.cursor_pos, self.next_char_pos()),
+                CTRL_L => self.clear_screen(),
+                CR if self.input_newline != InputNewline::Lf => {
+                    self.newline_pending = CR;
+                    self.enter();
                 },
-                _ => {
-                    debug!("unknown csi code {:02x}", c);
-                    (*self).input_mode = InputMode::Normal;
-                }
+                LF if self.input_newline != InputNewline::Cr => {
+                    self.newline_pending = LF;
+                    self.enter();
+                },
+                DEL | CTRL_H if self.cursor_pos > 0 => {
+                    // Back Space
+                    self.delete_range(self.prev_char_pos(), self.cursor_pos);
+                },
+                CTRL_A => self.move_home(),
+                CTRL_E => self.move_end(),
+                CTRL_B if self.cursor_pos > 0 => self.move_to(self.prev_char_pos()),
+                CTRL_F if self.cursor_pos < self.tail_pos => self.move_to(self.next_char_pos()),
+                CTRL_K => self.delete_range(self.cursor_pos, self.tail_pos),
+                CTRL_U => self.delete_range(0, self.tail_pos),
+                CTRL_W => self.delete_range(self.prev_word_pos(), self.cursor_pos),
+                CTRL_P => self.history_prev(),
+                CTRL_N => self.history_next(),
+                TAB => self.complete(),
+                _ => ()
             }
         }
+        else {
+            self.insert_bytes(&[c]);
+        }
     }
 
-    pub unsafe fn new(buffer: &'static mut [u8],
-                      prompt: &'static str,
-                      getc : Getc,
-                      mut putc : Putc,
-                      input_str: Option<InputStr>) -> Self {
+    fn input_alt(&mut self, c:u8){
+        self.newline_pending = NUL;
+        match c {
+            b'b' | b'B' => self.move_to(self.prev_word_pos()),
+            b'f' | b'F' => self.move_to(self.next_word_pos()),
+            DEL => self.delete_range(self.prev_word_pos(), self.cursor_pos),
+            _ => debug!("unknown alt key {:02x}", c)
+        }
+    }
 
-        for c in prompt.chars() {
-            (putc)(c as u8)
+    fn input_key(&mut self, key: Key, modifiers: Modifiers){
+        self.newline_pending = NUL;
+        let word_wise = modifiers.ctrl() || modifiers.alt();
+        match key {
+            Key::Left if word_wise => self.move_to(self.prev_word_pos()),
+            Key::Right if word_wise => self.move_to(self.next_word_pos()),
+            Key::Left => self.move_to(self.prev_char_pos()),
+            Key::Right => self.move_to(self.next_char_pos()),
+            Key::Up => self.history_prev(),
+            Key::Down => self.history_next(),
+            Key::Home => self.move_home(),
+            Key::End => self.move_end(),
+            Key::Delete => self.delete_range(self.cursor_pos, self.next_char_pos()),
+            _ => debug!("unhandled key {:?}", key)
         }
+    }
 
-        Self {
+    pub fn with_transport(buffer: &'static mut [u8],
+                          prompt: &'static str,
+                          transport: T,
+                          input_str: Option<InputStr>) -> Self {
+        let mut console = Self {
             cursor_pos : 0,
             tail_pos : 0,
-            input_mode: InputMode::Normal,
-            csi_pn: 0,
+            parser: Parser::new(),
+            utf8_pending: [0; 4],
+            utf8_len: 0,
             buffer,
             prompt,
-            getc,
-            putc,
+            secret: None,
+            input_newline: InputNewline::Any,
+            output_newline: OutputNewline::LfCr,
+            newline_pending: NUL,
+            running: false,
+            cancelled: false,
+            cancel: None,
+            page: None,
+            page_lines: 0,
+            size: None,
+            size_requery: true,
+            prompt_width: 0,
+            history: None,
+            port: Port::new(transport),
             input_str,
-        }
+        };
+        console.query_size();
+        console.put_prompt();
+        console.port.flush();
+        console
+    }
+
+    // The new prompt is shown on the next redraw, call redraw to show it
+    // right away.
+    pub fn set_prompt(&mut self, prompt: &'static str) {
+        self.prompt = prompt;
+    }
+
+    // Redraws the prompt and the line being edited.
+    pub fn redraw(&mut self) -> Result<(), T::Error> {
+        self.query_size();
+        self.move_to_line_start();
+        self.erase_below_cursor();
+        self.redraw_line();
+        self.port.flush();
+        self.port.result()
+    }
+
+    // Token set by Ctrl-C besides Output::cancelled, see CancelToken.
+    pub fn set_cancel_token(&mut self, cancel: &'static CancelToken) {
+        self.cancel = Some(cancel);
+    }
+
+    // Handles XON/XOFF from the terminal, XOFF holds back the output
+    // until XON. Off by default, the bytes reach the line editor then.
+    pub fn set_flow_control(&mut self, enable: bool) {
+        self.port.flow_control = enable;
+        self.port.paused = false;
+    }
+
+    // Stops the output of a command after `rows - 1` lines until a key is
+    // pressed: Space shows the next page, Enter one more line, q quits.
+    // None turns the pager off.
+    pub fn set_pager(&mut self, rows: Option<usize>) {
+        self.page = rows.filter(|&rows| rows > 1);
+    }
+
+    // Rows and columns of the terminal, None until it has answered the
+    // query sent at startup, on the first key or on a redraw.
+    pub fn size(&self) -> Option<(usize, usize)> {
+        self.size
     }
 
-    pub fn input(&mut self) {
-        if let Some(c) = (self.getc)() {
+    // A command started by input_str is still running.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn set_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    pub fn set_newline(&mut self, input: InputNewline, output: OutputNewline) {
+        self.input_newline = input;
+        self.output_newline = output;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn handler(&mut self) -> Option<&mut InputStr> {
+        self.input_str.as_mut()
+    }
+
+    // Reads the next line as a secret, see Output::read_secret. The line
+    // being edited is discarded.
+    pub fn read_secret(&mut self, mode: SecretMode, prompt: &'static str) {
+        self.move_to_line_start();
+        self.erase_below_cursor();
+        self.clear_buffer();
+        self.secret = Some((mode, prompt));
+        self.put_prompt();
+        self.port.flush();
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.port.transport
+    }
+
+    // Returns the first transport error since the last call.
+    pub fn input(&mut self) -> Result<(), T::Error> {
+        if self.running {
+            let c = self.port.read();
+            self.poll(c);
+        }
+        else if let Some(c) = self.port.read() {
             //debug!("input {:02x}", c);
-            if self.tail_pos >= self.buffer.len() { return (); }
-            match self.input_mode {
-                InputMode::Normal => self.input_normal(c),
-                InputMode::Esc => self.input_esc(c),
-                InputMode::CsiFirst => self.input_csi_first(c),
+            let event = self.parser.feed(c);
+            if self.size_requery && !matches!(event, None | Some(Event::CursorPosition(..))) {
+                self.size_requery = false;
+                self.query_size();
+            }
+            match event {
+                Some(Event::Byte(c)) => self.input_normal(c),
+                Some(Event::Key(key, modifiers)) => self.input_key(key, modifiers),
+                Some(Event::Alt(c)) => self.input_alt(c),
+                Some(Event::CursorPosition(row, column)) if self.size.is_none() && row >= MIN_ROWS && column >= MIN_COLUMNS => {
+                    self.size = Some((row as usize, column as usize));
+                },
+                Some(Event::CursorPosition(..)) => debug!("unexpected cursor position report"),
+                Some(Event::Unknown) => debug!("unknown escape sequence"),
+                None => ()
             }
         }
+        self.port.flush();
+        self.port.result()
     }
 
-    pub fn output(&mut self, message: &str) {
-        for c in message.bytes() {
-            (self.putc)(c)
+    // Prints a message above the line being edited and redraws the prompt,
+    // the partial input and the cursor below it.
+    pub fn write_above(&mut self, args: core::fmt::Arguments) -> Result<(), T::Error> {
+        self.move_to_line_start();
+        self.erase_below_cursor();
+        let mut output = Output::with_newline(&mut self.port, self.output_newline);
+        let _ = output.write_fmt(args);
+        if output.last != LF {
+            output.write_bytes(b"\n");
         }
+        self.redraw_line();
+        self.port.flush();
+        self.port.result()
+    }
+
+    pub fn print_above(&mut self, message: &str) -> Result<(), T::Error> {
+        self.write_above(format_args!("{}", message))
+    }
+
+    pub fn output(&mut self, message: &str) -> Result<(), T::Error> {
+        self.port.put(message.as_bytes());
+        self.port.flush();
+        self.port.result()
     }
 }
@@ -0,0 +1,408 @@
+// VT100/xterm escape sequence parser for key input.
+//
+// Bytes are fed one by one, an Event is returned when a byte or a whole
+// sequence has been recognized. Sequences which are not understood are
+// consumed up to their final byte and reported as Event::Unknown so that
+// none of their bytes reach the line buffer.
+//
+// Control characters are executed wherever they appear, as in the VT500
+// parser: inside a CSI the sequence goes on after them, after a lone ESC
+// or in ESC O the sequence is given up, e.g. ESC followed by CR is Enter.
+
+const ESC:u8 = b'\x1b';
+const BEL:u8 = b'\x07';
+const CAN:u8 = b'\x18';
+const SUB:u8 = b'\x1a';
+
+const MAX_PARAMS:usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const SHIFT: Modifiers = Modifiers(1);
+    pub const ALT: Modifiers = Modifiers(2);
+    pub const CTRL: Modifiers = Modifiers(4);
+
+    // xterm sends the modifier parameter as 1 + bits
+    fn from_param(pn: u16) -> Self {
+        Modifiers((pn.saturating_sub(1) & 0x07) as u8)
+    }
+
+    pub fn shift(&self) -> bool { self.0 & Self::SHIFT.0 != 0 }
+    pub fn alt(&self) -> bool { self.0 & Self::ALT.0 != 0 }
+    pub fn ctrl(&self) -> bool { self.0 & Self::CTRL.0 != 0 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up, Down, Right, Left,
+    Home, End, Insert, Delete, PageUp, PageDown,
+    BackTab,
+    Function(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    // Any byte outside of an escape sequence, including control characters.
+    Byte(u8),
+    Key(Key, Modifiers),
+    // ESC followed by a printable character or DEL (Meta/Alt + key).
+    Alt(u8),
+    // ESC [ row ; column R, the reply to ESC [ 6 n. xterm sends F3 with
+    // modifiers as ESC [ 1 ; m R as well, the console only takes a
+    // reply on a row below the first as the size.
+    CursorPosition(u16, u16),
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    Csi,
+    CsiIgnore,
+    Ss3,
+    String,
+    StringEsc,
+}
+
+pub struct Parser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    nparams: usize,
+    private: bool,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            nparams: 0,
+            private: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = State::Ground;
+    }
+
+    // True while a sequence has been started but not completed.
+    pub fn in_sequence(&self) -> bool {
+        self.state != State::Ground
+    }
+
+    fn start_params(&mut self) {
+        self.params = [0; MAX_PARAMS];
+        self.nparams = 0;
+        self.private = false;
+    }
+
+    fn param(&self, n: usize) -> u16 {
+        if n < self.nparams { self.params[n] } else { 0 }
+    }
+
+    fn done(&mut self, event: Event) -> Option<Event> {
+        self.state = State::Ground;
+        Some(event)
+    }
+
+    pub fn feed(&mut self, c: u8) -> Option<Event> {
+        match self.state {
+            State::Ground => {
+                if c == ESC {
+                    self.state = State::Escape;
+                    None
+                }
+                else {
+                    Some(Event::Byte(c))
+                }
+            },
+            State::Escape => self.escape(c),
+            State::EscapeIntermediate => {
+                match c {
+                    ESC => { self.state = State::Escape; None },
+                    CAN | SUB => self.done(Event::Unknown),
+                    0x00..=0x1f => self.done(Event::Byte(c)),
+                    0x30..=0x7e => self.done(Event::Unknown),
+                    _ => None
+                }
+            },
+            State::Csi => self.csi(c),
+            State::CsiIgnore => {
+                match c {
+                    ESC => { self.state = State::Escape; None },
+                    CAN | SUB | 0x40..=0x7e => self.done(Event::Unknown),
+                    0x00..=0x1f => Some(Event::Byte(c)),
+                    _ => None
+                }
+            },
+            State::Ss3 => self.ss3(c),
+            State::String => {
+                match c {
+                    BEL | CAN | SUB => self.done(Event::Unknown),
+                    ESC => { self.state = State::StringEsc; None },
+                    _ => None
+                }
+            },
+            State::StringEsc => {
+                // ESC \ is the string terminator, anything else aborts the string
+                if c == b'\\' {
+                    self.done(Event::Unknown)
+                }
+                else {
+                    self.state = State::Escape;
+                    self.escape(c).or(Some(Event::Unknown))
+                }
+            },
+        }
+    }
+
+    fn escape(&mut self, c: u8) -> Option<Event> {
+        match c {
+            b'[' => {
+                self.start_params();
+                self.state = State::Csi;
+                None
+            },
+            b'O' => {
+                self.start_params();
+                self.state = State::Ss3;
+                None
+            },
+            b']' | b'P' | b'X' | b'^' | b'_' => {
+                // OSC, DCS, SOS, PM, APC
+                self.state = State::String;
+                None
+            },
+            ESC => None,
+            CAN | SUB => self.done(Event::Unknown),
+            0x00..=0x1f => self.done(Event::Byte(c)),
+            0x20..=0x2f => {
+                self.state = State::EscapeIntermediate;
+                None
+            },
+            0x30..=0x7f => self.done(Event::Alt(c)),
+            _ => self.done(Event::Unknown),
+        }
+    }
+
+    fn csi(&mut self, c: u8) -> Option<Event> {
+        match c {
+            b'0'..=b'9' => {
+                if self.nparams == 0 { self.nparams = 1; }
+                if self.nparams <= MAX_PARAMS {
+                    let pn = &mut self.params[self.nparams - 1];
+                    *pn = pn.saturating_mul(10).saturating_add((c - b'0') as u16);
+                }
+                None
+            },
+            b';' | b':' => {
+                if self.nparams == 0 { self.nparams = 1; }
+                self.nparams += 1;
+                None
+            },
+            b'<'..=b'?' => {
+                self.private = true;
+                None
+            },
+            0x20..=0x2f => {
+                // intermediate bytes are not used by any key
+                self.state = State::CsiIgnore;
+                None
+            },
+            0x40..=0x7e => {
+                let event = if self.private { Event::Unknown } else { self.csi_final(c) };
+                self.done(event)
+            },
+            ESC => { self.state = State::Escape; None },
+            CAN | SUB => self.done(Event::Unknown),
+            0x00..=0x1f => Some(Event::Byte(c)),
+            _ => None,
+        }
+    }
+
+    fn csi_final(&self, c: u8) -> Event {
+        if c == b'R' {
+            return Event::CursorPosition(self.param(0), self.param(1));
+        }
+        let modifiers = Modifiers::from_param(self.param(1));
+        let key = match c {
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            b'Z' => Key::BackTab,
+            b'P' => Key::Function(1),
+            b'Q' => Key::Function(2),
+            b'S' => Key::Function(4),
+            b'~' => match self.param(0) {
+                1 | 7 => Key::Home,
+                2 => Key::Insert,
+                3 => Key::Delete,
+                4 | 8 => Key::End,
+                5 => Key::PageUp,
+                6 => Key::PageDown,
+                pn @ 11..=15 => Key::Function((pn - 10) as u8),
+                pn @ 17..=21 => Key::Function((pn - 11) as u8),
+                pn @ 23..=24 => Key::Function((pn - 12) as u8),
+                _ => return Event::Unknown
+            },
+            _ => return Event::Unknown
+        };
+        Event::Key(key, modifiers)
+    }
+
+    fn ss3(&mut self, c: u8) -> Option<Event> {
+        let key = match c {
+            // some terminals send the modifier as ESC O 5 C
+            b'0'..=b'9' => {
+                self.nparams = 2;
+                self.params[1] = self.params[1].saturating_mul(10).saturating_add((c - b'0') as u16);
+                return None;
+            },
+            ESC => { self.state = State::Escape; return None; },
+            CAN | SUB => return self.done(Event::Unknown),
+            0x00..=0x1f => return self.done(Event::Byte(c)),
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            b'P' => Key::Function(1),
+            b'Q' => Key::Function(2),
+            b'R' => Key::Function(3),
+            b'S' => Key::Function(4),
+            _ => return self.done(Event::Unknown)
+        };
+        let modifiers = Modifiers::from_param(self.param(1));
+        self.done(Event::Key(key, modifiers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &[u8]) -> ([Option<Event>; 8], usize) {
+        let mut parser = Parser::new();
+        let mut events = [None; 8];
+        let mut n = 0;
+        for &c in input {
+            if let Some(event) = parser.feed(c) {
+                events[n] = Some(event);
+                n += 1;
+            }
+        }
+        (events, n)
+    }
+
+    fn single(input: &[u8]) -> Event {
+        let (events, n) = parse(input);
+        assert_eq!(n, 1, "{:?}", events);
+        events[0].unwrap()
+    }
+
+    #[test]
+    fn plain_bytes() {
+        let (events, n) = parse(b"a\r\x7f");
+        assert_eq!(n, 3);
+        assert_eq!(events[0], Some(Event::Byte(b'a')));
+        assert_eq!(events[1], Some(Event::Byte(b'\r')));
+        assert_eq!(events[2], Some(Event::Byte(0x7f)));
+    }
+
+    #[test]
+    fn csi_keys() {
+        assert_eq!(single(b"\x1b[A"), Event::Key(Key::Up, Modifiers::NONE));
+        assert_eq!(single(b"\x1b[D"), Event::Key(Key::Left, Modifiers::NONE));
+        assert_eq!(single(b"\x1b[3~"), Event::Key(Key::Delete, Modifiers::NONE));
+        assert_eq!(single(b"\x1b[15~"), Event::Key(Key::Function(5), Modifiers::NONE));
+        assert_eq!(single(b"\x1b[24~"), Event::Key(Key::Function(12), Modifiers::NONE));
+    }
+
+    #[test]
+    fn csi_modifiers() {
+        assert_eq!(single(b"\x1b[1;5C"), Event::Key(Key::Right, Modifiers::CTRL));
+        assert_eq!(single(b"\x1b[1;3D"), Event::Key(Key::Left, Modifiers::ALT));
+        assert_eq!(single(b"\x1b[3;2~"), Event::Key(Key::Delete, Modifiers::SHIFT));
+        assert!(single(b"\x1b[1;7A") == Event::Key(Key::Up, Modifiers(6)));
+    }
+
+    #[test]
+    fn cursor_position_report() {
+        assert_eq!(single(b"\x1b[24;80R"), Event::CursorPosition(24, 80));
+        assert_eq!(single(b"\x1b[R"), Event::CursorPosition(0, 0));
+    }
+
+    #[test]
+    fn ss3_keys() {
+        assert_eq!(single(b"\x1bOH"), Event::Key(Key::Home, Modifiers::NONE));
+        assert_eq!(single(b"\x1bOR"), Event::Key(Key::Function(3), Modifiers::NONE));
+        assert_eq!(single(b"\x1bO5C"), Event::Key(Key::Right, Modifiers::CTRL));
+    }
+
+    #[test]
+    fn alt_keys() {
+        assert_eq!(single(b"\x1bb"), Event::Alt(b'b'));
+        assert_eq!(single(b"\x1b\x7f"), Event::Alt(0x7f));
+    }
+
+    #[test]
+    fn unknown_sequences_are_swallowed() {
+        assert_eq!(single(b"\x1b[?1049h"), Event::Unknown);
+        assert_eq!(single(b"\x1b[1;2;3;4;5;6;7x"), Event::Unknown);
+        assert_eq!(single(b"\x1b[99~"), Event::Unknown);
+        assert_eq!(single(b"\x1b]0;title\x07"), Event::Unknown);
+        assert_eq!(single(b"\x1bP1$r0m\x1b\\"), Event::Unknown);
+        assert_eq!(single(b"\x1b(B"), Event::Unknown);
+        assert_eq!(single(b"\x1b[1 q"), Event::Unknown);
+    }
+
+    #[test]
+    fn sequence_interrupted_by_escape() {
+        let (events, n) = parse(b"\x1b[12\x1b[Bx");
+        assert_eq!(n, 2);
+        assert_eq!(events[0], Some(Event::Key(Key::Down, Modifiers::NONE)));
+        assert_eq!(events[1], Some(Event::Byte(b'x')));
+    }
+
+    #[test]
+    fn control_after_escape_gives_up_the_sequence() {
+        let (events, n) = parse(b"\x1b\rdef");
+        assert_eq!(n, 4);
+        assert_eq!(events[0], Some(Event::Byte(b'\r')));
+        assert_eq!(events[1], Some(Event::Byte(b'd')));
+        assert_eq!(single(b"\x1bO\x08"), Event::Byte(0x08));
+        assert_eq!(single(b"\x1b(\t"), Event::Byte(b'\t'));
+    }
+
+    #[test]
+    fn control_inside_csi_is_executed() {
+        let (events, n) = parse(b"\x1b[1\x08;5C");
+        assert_eq!(n, 2);
+        assert_eq!(events[0], Some(Event::Byte(0x08)));
+        assert_eq!(events[1], Some(Event::Key(Key::Right, Modifiers::CTRL)));
+        let (events, n) = parse(b"\x1b[1 \rq");
+        assert_eq!(n, 2);
+        assert_eq!(events[0], Some(Event::Byte(b'\r')));
+        assert_eq!(events[1], Some(Event::Unknown));
+    }
+
+    #[test]
+    fn huge_parameter_saturates() {
+        assert_eq!(single(b"\x1b[99999999999999;5C"), Event::Key(Key::Right, Modifiers::CTRL));
+    }
+}
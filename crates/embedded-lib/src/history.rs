@@ -0,0 +1,119 @@
+// Command history for Console.
+//
+// The storage is split into `depth + 1` fixed size slots. Slots 0..depth hold
+// the recorded lines as a ring, the last slot keeps the line being edited while
+// the user walks through the history. Lines are NUL padded like the console
+// buffer, so no length field is needed.
+
+pub struct History {
+    storage: &'static mut [u8],
+    depth: usize,
+    slot_len: usize,
+    newest: usize,
+    count: usize,
+    cursor: Option<usize>,
+}
+
+impl History {
+
+    pub fn new(storage: &'static mut [u8], depth: usize) -> Self {
+        let slot_len = if depth == 0 { 0 } else { storage.len() / (depth + 1) };
+        storage.fill(0);
+        History {
+            storage,
+            depth,
+            slot_len,
+            newest: 0,
+            count: 0,
+            cursor: None,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.storage.fill(0);
+        self.newest = 0;
+        self.count = 0;
+        self.cursor = None;
+    }
+
+    fn slot(&self, index: usize) -> &[u8] {
+        let start = index * self.slot_len;
+        let slot = &self.storage[start..start + self.slot_len];
+        let len = slot.iter().position(|b| *b == 0).unwrap_or(slot.len());
+        &slot[..len]
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut [u8] {
+        let start = index * self.slot_len;
+        &mut self.storage[start..start + self.slot_len]
+    }
+
+    fn store(&mut self, index: usize, line: &[u8]) {
+        let slot = self.slot_mut(index);
+        slot.fill(0);
+        slot[..line.len()].copy_from_slice(line);
+    }
+
+    // n = 0 is the most recent entry.
+    pub fn get(&self, n: usize) -> Option<&[u8]> {
+        if n >= self.count { return None; }
+        let index = (self.newest + self.depth - n) % self.depth;
+        Some(self.slot(index))
+    }
+
+    pub fn push(&mut self, line: &[u8]) {
+        self.cursor = None;
+        if line.is_empty() || line.len() > self.slot_len { return; }
+        if self.get(0) == Some(line) { return; }
+        let index = if self.count == 0 { 0 } else { (self.newest + 1) % self.depth };
+        self.store(index, line);
+        self.newest = index;
+        if self.count < self.depth { self.count += 1; }
+    }
+
+    // Returns the older entry, `current` is kept to come back to it later.
+    pub fn older(&mut self, current: &[u8]) -> Option<&[u8]> {
+        let next = match self.cursor {
+            None => 0,
+            Some(n) => n + 1,
+        };
+        if next >= self.count { return None; }
+        if self.cursor.is_none() {
+            let scratch = if current.len() > self.slot_len { &current[..0] } else { current };
+            self.store(self.depth, scratch);
+        }
+        self.cursor = Some(next);
+        self.get(next)
+    }
+
+    // Returns the newer entry, or the line which was being edited.
+    pub fn newer(&mut self) -> Option<&[u8]> {
+        match self.cursor {
+            None => None,
+            Some(0) => {
+                self.cursor = None;
+                Some(self.slot(self.depth))
+            },
+            Some(n) => {
+                self.cursor = Some(n - 1);
+                self.get(n - 1)
+            }
+        }
+    }
+
+    pub fn reset_cursor(&mut self) {
+        self.cursor = None;
+    }
+}
@@ -1,4 +1,17 @@
-#![no_std]
+#![cfg_attr(not(any(test, feature = "std", loom)), no_std)]
 
+#[cfg(feature = "embedded-io-async")]
+pub mod async_console;
+pub mod command;
 pub mod console;
+pub mod escape;
+pub mod history;
+pub mod memory;
+pub mod script;
 pub mod shared_ringbuffer;
+pub mod spsc_ringbuffer;
+pub mod stm32h7;
+#[cfg(feature = "std")]
+pub mod testing;
+pub mod transport;
+pub mod unicode;
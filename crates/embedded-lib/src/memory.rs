@@ -0,0 +1,265 @@
+// Memory and register inspection commands, md, mw and regdump.
+//
+// The context says which memory they may touch, see MemoryMap. Any other
+// address is refused, reading an unmapped one ends in a bus fault.
+
+use core::fmt::Write;
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::command::{Args, Command, CommandError, FromArg};
+use crate::console::Output;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Half,
+    Word,
+}
+
+impl Width {
+    pub fn bytes(self) -> usize {
+        match self {
+            Width::Byte => 1,
+            Width::Half => 2,
+            Width::Word => 4,
+        }
+    }
+
+    fn max(self) -> u32 {
+        match self {
+            Width::Byte => u8::MAX as u32,
+            Width::Half => u16::MAX as u32,
+            Width::Word => u32::MAX,
+        }
+    }
+
+    unsafe fn read(self, address: usize) -> u32 {
+        match self {
+            Width::Byte => read_volatile(address as *const u8) as u32,
+            Width::Half => read_volatile(address as *const u16) as u32,
+            Width::Word => read_volatile(address as *const u32),
+        }
+    }
+
+    unsafe fn write(self, address: usize, value: u32) {
+        match self {
+            Width::Byte => write_volatile(address as *mut u8, value as u8),
+            Width::Half => write_volatile(address as *mut u16, value as u16),
+            Width::Word => write_volatile(address as *mut u32, value),
+        }
+    }
+}
+
+impl FromArg<'_> for Width {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "b" => Some(Width::Byte),
+            "h" => Some(Width::Half),
+            "w" => Some(Width::Word),
+            _ => None
+        }
+    }
+}
+
+// RAM, flash or any other memory md may read.
+pub struct Region {
+    pub name: &'static str,
+    pub start: usize,
+    pub size: usize,
+    pub writable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    ReadWrite,
+    ReadOnly,
+    WriteOnly,
+    // reading changes the state, e.g. pops a receive FIFO or takes a
+    // semaphore. regdump leaves it alone, md still reads it.
+    ReadSideEffect,
+}
+
+pub struct Register {
+    pub name: &'static str,
+    pub offset: usize,
+    pub access: Access,
+}
+
+impl Register {
+    pub const fn new(name: &'static str, offset: usize, access: Access) -> Self {
+        Register { name, offset, access }
+    }
+}
+
+// Register block of a peripheral, md and mw may access all of it.
+pub struct Peripheral {
+    pub name: &'static str,
+    pub base: usize,
+    pub size: usize,
+    pub registers: &'static [Register],
+}
+
+impl Peripheral {
+    pub fn register(&self, name: &str) -> Option<&'static Register> {
+        self.registers.iter().find(|register| register.name.eq_ignore_ascii_case(name))
+    }
+}
+
+// Implemented by the context of a shell using the commands below.
+pub trait MemoryMap {
+    fn regions(&self) -> &[Region];
+
+    fn peripherals(&self) -> &[Peripheral] {
+        &[]
+    }
+
+    fn peripheral(&self, name: &str) -> Option<&Peripheral> {
+        self.peripherals().iter().find(|peripheral| peripheral.name.eq_ignore_ascii_case(name))
+    }
+}
+
+// Checks that `count` items of `width` from `address` lie in one region
+// or register block.
+fn check<C: MemoryMap>(context: &C, address: usize, width: Width, count: usize, write: bool) -> Result<(), CommandError> {
+    if !address.is_multiple_of(width.bytes()) {
+        return Err(CommandError::Failed("unaligned address"));
+    }
+    let end = count.checked_mul(width.bytes())
+        .and_then(|len| address.checked_add(len))
+        .ok_or(CommandError::Failed("address not mapped"))?;
+    let inside = |start: usize, size: usize| address >= start && end - start <= size;
+    if context.peripherals().iter().any(|peripheral| inside(peripheral.base, peripheral.size)) {
+        return Ok(());
+    }
+    match context.regions().iter().find(|region| inside(region.start, region.size)) {
+        Some(region) if write && !region.writable => Err(CommandError::Failed("region is read-only")),
+        Some(_) => Ok(()),
+        None => Err(CommandError::Failed("address not mapped")),
+    }
+}
+
+const LINE_BYTES: usize = 16;
+
+fn run_md<C: MemoryMap>(context: &mut C, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    let start: usize = args.required()?;
+    let mut width = Width::Word;
+    let mut count = None;
+    // [count] [b|h|w] in either order
+    while let Some(word) = args.next_str()? {
+        match (Width::from_arg(word), usize::from_arg(word)) {
+            (Some(w), _) => width = w,
+            (None, Some(n)) if count.is_none() => count = Some(n),
+            _ => return Err(CommandError::InvalidArgument(args.index() - 1)),
+        }
+    }
+    let count = count.unwrap_or(4 * LINE_BYTES / width.bytes());
+    check(context, start, width, count, false)?;
+
+    let per_line = LINE_BYTES / width.bytes();
+    let mut line = [0u8; LINE_BYTES];
+    let mut i = 0;
+    while i < count && !output.cancelled() {
+        let n = per_line.min(count - i);
+        let _ = write!(output, "{:08x}:", start + i * width.bytes());
+        for (j, c) in line.iter_mut().enumerate().take(n) {
+            let value = unsafe { width.read(start + (i + j) * width.bytes()) };
+            let _ = write!(output, " {:01$x}", value, 2 * width.bytes());
+            *c = value as u8;
+        }
+        if width == Width::Byte {
+            let _ = write!(output, "{:1$}  ", "", 3 * (per_line - n));
+            for &c in &line[..n] {
+                let _ = output.write_char(if c.is_ascii_graphic() || c == b' ' { c as char } else { '.' });
+            }
+        }
+        let _ = writeln!(output);
+        i += n;
+    }
+    Ok(())
+}
+
+fn run_mw<C: MemoryMap>(context: &mut C, args: &mut Args, _output: &mut Output) -> Result<(), CommandError> {
+    let address: usize = args.required()?;
+    let value: u32 = args.required()?;
+    let width = args.optional()?.unwrap_or(Width::Word);
+    args.finish()?;
+    if value > width.max() {
+        return Err(CommandError::InvalidArgument(2));
+    }
+    check(context, address, width, 1, true)?;
+    unsafe { width.write(address, value) };
+    Ok(())
+}
+
+fn dump_register(peripheral: &Peripheral, register: &Register, name_width: usize, output: &mut Output) {
+    let address = peripheral.base + register.offset;
+    let _ = write!(output, "  {:2$}  {:08x}  ", register.name, address, name_width);
+    let _ = match register.access {
+        Access::ReadWrite | Access::ReadOnly => writeln!(output, "{:08x}", unsafe { Width::Word.read(address) }),
+        Access::WriteOnly => writeln!(output, "(write-only)"),
+        Access::ReadSideEffect => writeln!(output, "(not read)"),
+    };
+}
+
+fn run_regdump<C: MemoryMap>(context: &mut C, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    let name = match args.next_str()? {
+        Some(name) => name,
+        None => {
+            for peripheral in context.peripherals() {
+                let _ = writeln!(output, "{:8}  {:08x}", peripheral.name, peripheral.base);
+            }
+            return Ok(());
+        }
+    };
+    let peripheral = context.peripheral(name).ok_or(CommandError::InvalidArgument(1))?;
+    if let Some(name) = args.next_str()? {
+        args.finish()?;
+        let register = peripheral.register(name).ok_or(CommandError::InvalidArgument(2))?;
+        return match register.access {
+            Access::WriteOnly => Err(CommandError::Failed("write-only register")),
+            Access::ReadSideEffect => Err(CommandError::Failed("reading it has side effects, use md")),
+            _ => {
+                dump_register(peripheral, register, register.name.len(), output);
+                Ok(())
+            }
+        };
+    }
+    let name_width = peripheral.registers.iter().map(|register| register.name.len()).fold(0, usize::max);
+    let _ = writeln!(output, "{} @ {:08x}", peripheral.name, peripheral.base);
+    for register in peripheral.registers {
+        if output.cancelled() { break; }
+        dump_register(peripheral, register, name_width, output);
+    }
+    Ok(())
+}
+
+fn complete_regdump<C: MemoryMap>(context: &C, args: &mut Args, candidate: &mut dyn FnMut(&'static str)) {
+    match args.next_str() {
+        Ok(None) => {
+            for peripheral in context.peripherals() {
+                candidate(peripheral.name);
+            }
+        },
+        Ok(Some(name)) => {
+            if let (Some(peripheral), Ok(None)) = (context.peripheral(name), args.next_str()) {
+                for register in peripheral.registers {
+                    candidate(register.name);
+                }
+            }
+        },
+        Err(_) => ()
+    }
+}
+
+pub const fn md<C: MemoryMap>() -> Command<C> {
+    Command::new("md", "md <address> [count] [b|h|w]  display memory", run_md::<C>)
+}
+
+pub const fn mw<C: MemoryMap>() -> Command<C> {
+    Command::new("mw", "mw <address> <value> [b|h|w]  write memory", run_mw::<C>)
+}
+
+pub const fn regdump<C: MemoryMap>() -> Command<C> {
+    Command::new("regdump", "regdump [peripheral [register]]  dump registers", run_regdump::<C>)
+        .with_complete(complete_regdump::<C>)
+}
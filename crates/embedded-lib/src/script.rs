@@ -0,0 +1,368 @@
+// Scripting layer on top of Shell for bring-up sequences:
+//
+//   led on; sleep 100; led off
+//   alias blink "led on; sleep 100; led off; sleep 100"
+//   repeat 20 blink; led on
+//
+// `repeat N` runs the rest of the line N times. Aliases live in a table of
+// ALIASES slots of LEN bytes, LEN also bounds the length of a line. A
+// script runs as a long running command of the console, sleep does not
+// block and Ctrl-C stops the script.
+
+use core::fmt::Write;
+use core::str;
+
+use crate::command::{Args, CommandError, Shell};
+use crate::console::{InputHandler, Output};
+
+// Milliseconds from any origin, it may wrap.
+pub type ClockFn = fn() -> u32;
+
+// nested aliases and repeats
+const DEPTH: usize = 4;
+
+const BUILTINS: [&str; 4] = ["alias", "repeat", "sleep", "unalias"];
+
+struct Alias<const LEN: usize> {
+    // name followed by the body
+    text: [u8; LEN],
+    name_len: usize,
+    len: usize,
+}
+
+impl<const LEN: usize> Alias<LEN> {
+    const EMPTY: Self = Alias { text: [0; LEN], name_len: 0, len: 0 };
+
+    fn name(&self) -> &str {
+        str::from_utf8(&self.text[..self.name_len]).unwrap_or("")
+    }
+
+    fn body(&self) -> &str {
+        str::from_utf8(&self.text[self.name_len..self.len]).unwrap_or("")
+    }
+}
+
+pub struct Aliases<const N: usize, const LEN: usize> {
+    slots: [Alias<LEN>; N],
+}
+
+impl<const N: usize, const LEN: usize> Aliases<N, LEN> {
+    pub const fn new() -> Self {
+        Aliases { slots: [Alias::EMPTY; N] }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|alias| alias.name_len > 0 && alias.name() == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.find(name).map(|i| self.slots[i].body())
+    }
+
+    pub fn define(&mut self, name: &str, body: &str) -> Result<(), CommandError> {
+        if name.is_empty() || BUILTINS.contains(&name) {
+            return Err(CommandError::InvalidArgument(1));
+        }
+        if name.len() + body.len() > LEN {
+            return Err(CommandError::Failed("alias too long"));
+        }
+        let i = match self.find(name).or_else(|| self.slots.iter().position(|alias| alias.name_len == 0)) {
+            Some(i) => i,
+            None => return Err(CommandError::Failed("alias table full")),
+        };
+        let alias = &mut self.slots[i];
+        alias.text[..name.len()].copy_from_slice(name.as_bytes());
+        alias.text[name.len()..name.len() + body.len()].copy_from_slice(body.as_bytes());
+        alias.name_len = name.len();
+        alias.len = name.len() + body.len();
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.find(name) {
+            Some(i) => {
+                self.slots[i] = Alias::EMPTY;
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.slots.iter().filter(|alias| alias.name_len > 0).map(|alias| (alias.name(), alias.body()))
+    }
+}
+
+impl<const N: usize, const LEN: usize> Default for Aliases<N, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Source {
+    Line,
+    Alias(usize),
+}
+
+// Part of a line or of an alias body being run `remaining` more times.
+#[derive(Clone, Copy)]
+struct Frame {
+    source: Source,
+    start: usize,
+    pos: usize,
+    end: usize,
+    remaining: u32,
+}
+
+// Splits off the command at the start of `text`, returns its length and
+// where the next one starts.
+fn split_command(text: &str) -> (usize, usize) {
+    let mut quote = None;
+    for (p, c) in text.char_indices() {
+        match (quote, c) {
+            (None, ';') => return (p, p + 1),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => ()
+        }
+    }
+    (text.len(), text.len())
+}
+
+pub struct ScriptShell<'a, C, const ALIASES: usize, const LEN: usize> {
+    shell: Shell<'a, C>,
+    aliases: Aliases<ALIASES, LEN>,
+    now: ClockFn,
+    line: [u8; LEN],
+    line_len: usize,
+    frames: [Frame; DEPTH],
+    depth: usize,
+    // start and length of the running sleep
+    sleep: Option<(u32, u32)>,
+    // a shell command keeps running
+    waiting: bool,
+}
+
+impl<'a, C, const ALIASES: usize, const LEN: usize> ScriptShell<'a, C, ALIASES, LEN> {
+    pub fn new(shell: Shell<'a, C>, now: ClockFn) -> Self {
+        ScriptShell {
+            shell,
+            aliases: Aliases::new(),
+            now,
+            line: [0; LEN],
+            line_len: 0,
+            frames: [Frame { source: Source::Line, start: 0, pos: 0, end: 0, remaining: 0 }; DEPTH],
+            depth: 0,
+            sleep: None,
+            waiting: false,
+        }
+    }
+
+    pub fn shell(&mut self) -> &mut Shell<'a, C> {
+        &mut self.shell
+    }
+
+    pub fn aliases(&mut self) -> &mut Aliases<ALIASES, LEN> {
+        &mut self.aliases
+    }
+
+    fn text(&self, source: Source) -> &str {
+        match source {
+            Source::Line => str::from_utf8(&self.line[..self.line_len]).unwrap_or(""),
+            Source::Alias(i) => self.aliases.slots[i].body(),
+        }
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<(), CommandError> {
+        if self.depth == DEPTH {
+            return Err(CommandError::Failed("nested too deep"));
+        }
+        self.frames[self.depth] = frame;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.depth = 0;
+        self.sleep = None;
+        self.waiting = false;
+    }
+
+    fn alias(&mut self, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+        let name = match args.next_str()? {
+            Some(name) => name,
+            None => {
+                for (name, body) in self.aliases.iter() {
+                    let _ = writeln!(output, "{} = {}", name, body);
+                }
+                return Ok(());
+            }
+        };
+        let body = match args.next_str()? {
+            Some(body) => body,
+            None => {
+                let body = self.aliases.get(name).ok_or(CommandError::InvalidArgument(1))?;
+                let _ = writeln!(output, "{} = {}", name, body);
+                return Ok(());
+            }
+        };
+        args.finish()?;
+        self.aliases.define(name, body)
+    }
+
+    // Runs the next command of the innermost frame.
+    fn step(&mut self, output: &mut Output) -> Result<(), CommandError> {
+        let top = self.depth - 1;
+        let frame = self.frames[top];
+        let text = self.text(frame.source);
+        let end = frame.end.min(text.len());
+        if !text.is_char_boundary(frame.pos) || !text.is_char_boundary(end) {
+            // the alias has been redefined while it runs
+            self.depth -= 1;
+            return Ok(());
+        }
+        if frame.pos >= end {
+            if frame.remaining > 1 {
+                self.frames[top].remaining -= 1;
+                self.frames[top].pos = frame.start;
+                // one round per poll so that a long repeat can be stopped
+                output.keep_running();
+            }
+            else {
+                self.depth -= 1;
+            }
+            return Ok(());
+        }
+        let (len, next) = split_command(&text[frame.pos..end]);
+        // the command has to be copied, running it may change an alias
+        let mut copy = [0u8; LEN];
+        copy[..len].copy_from_slice(&text.as_bytes()[frame.pos..frame.pos + len]);
+        self.frames[top].pos = frame.pos + next;
+        let command = str::from_utf8(&copy[..len]).unwrap_or("");
+        let mut args = Args::new(command);
+        let name = match args.next_str()? {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        match name {
+            "sleep" => {
+                let ms = args.required::<u32>()?;
+                args.finish()?;
+                self.sleep = Some(((self.now)(), ms));
+            },
+            "repeat" => {
+                let count = args.required::<u32>()?;
+                // the rest of the frame is repeated, not only this command
+                let start = frame.pos + command.trim_end().len() - args.rest().len();
+                self.frames[top].pos = end;
+                if count > 0 {
+                    self.push(Frame { source: frame.source, start, pos: start, end, remaining: count })?;
+                }
+            },
+            "alias" => self.alias(&mut args, output)?,
+            "unalias" => {
+                let name = args.required::<&str>()?;
+                args.finish()?;
+                if !self.aliases.remove(name) {
+                    return Err(CommandError::InvalidArgument(1));
+                }
+            },
+            _ => match self.aliases.find(name) {
+                Some(i) => {
+                    args.finish()?;
+                    let end = self.aliases.slots[i].body().len();
+                    self.push(Frame { source: Source::Alias(i), start: 0, pos: 0, end, remaining: 1 })?;
+                },
+                None => {
+                    self.shell.input_str(command, output);
+                    if output.running() {
+                        self.waiting = true;
+                    }
+                    else if self.shell.last_error().is_some() {
+                        // the shell has reported it
+                        self.stop();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Runs the script until it is done or has to wait.
+    fn run(&mut self, output: &mut Output) {
+        loop {
+            if output.cancelled() {
+                self.stop();
+                return;
+            }
+            if let Some((start, ms)) = self.sleep {
+                if (self.now)().wrapping_sub(start) < ms {
+                    output.keep_running();
+                    return;
+                }
+                self.sleep = None;
+            }
+            if self.waiting || self.depth == 0 {
+                return;
+            }
+            let frame = self.frames[self.depth - 1];
+            if let Err(e) = self.step(output) {
+                let text = self.text(frame.source);
+                let command = &text[frame.pos.min(text.len())..];
+                let name = Args::new(command).next_str().ok().flatten().unwrap_or("");
+                let _ = writeln!(output, "{}: {}", name, e);
+                self.stop();
+            }
+            if output.running() {
+                return;
+            }
+        }
+    }
+}
+
+impl<C, const ALIASES: usize, const LEN: usize> InputHandler for ScriptShell<'_, C, ALIASES, LEN> {
+    fn input_str(&mut self, line: &str, output: &mut Output) {
+        self.stop();
+        if line.len() > LEN {
+            let _ = writeln!(output, "line too long");
+            return;
+        }
+        self.line[..line.len()].copy_from_slice(line.as_bytes());
+        self.line_len = line.len();
+        self.frames[0] = Frame { source: Source::Line, start: 0, pos: 0, end: line.len(), remaining: 1 };
+        self.depth = 1;
+        self.run(output);
+    }
+
+    fn poll(&mut self, output: &mut Output) {
+        if self.waiting {
+            self.shell.poll(output);
+            if output.running() {
+                return;
+            }
+            self.waiting = false;
+            if self.shell.last_error().is_some() {
+                self.stop();
+            }
+        }
+        self.run(output);
+    }
+
+    fn input_secret(&mut self, secret: &str, output: &mut Output) {
+        self.shell.input_secret(secret, output);
+    }
+
+    fn prompt(&mut self, prompt: &str, output: &mut Output) {
+        self.shell.prompt(prompt, output);
+    }
+
+    fn complete(&mut self, line: &str, candidate: &mut dyn FnMut(&'static str)) {
+        if !line.contains(' ') {
+            for builtin in BUILTINS {
+                candidate(builtin);
+            }
+        }
+        self.shell.complete(line, candidate);
+    }
+}
@@ -1,9 +1,27 @@
-
-use core::{slice,fmt};
+//! Message ring buffers in memory shared by the two cores.
+//!
+//! # Creating and attaching
+//!
+//! One core lays a buffer out with `create`, the other finds it with
+//! `attach`. A header at the start of the memory carries a magic number,
+//! the layout version, the slot size S, the number of slots N and the
+//! `owner` the creating core gave. `attach` refuses memory without the
+//! magic or laid out for another version or geometry, see [`LayoutError`].
+//!
+//! # Safety
+//!
+//! The memory given to `create` and `attach` must be `buffer_size` bytes
+//! shared with the peer core which stay mapped for the rest of the program.
+//! The peer must not use it until it is created.
+
+use core::{mem,slice,fmt};
+use core::marker::PhantomData;
+use core::ptr::{self, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
 use log::debug;
 
 pub enum SharedRingBufferError {
-    NoSpace, NoData, CantLock,
+    NoSpace, NoData, CantLock, MessageTooLarge, PartialMessage,
 }
 
 impl fmt::Display for SharedRingBufferError {
@@ -11,85 +29,438 @@ impl fmt::Display for SharedRingBufferError {
         match self {
             SharedRingBufferError::NoSpace => write!(f, "No Space"),
             SharedRingBufferError::NoData => write!(f, "No Data"),
-            SharedRingBufferError::CantLock => write!(f, "Can't lock")
+            SharedRingBufferError::CantLock => write!(f, "Can't lock"),
+            SharedRingBufferError::MessageTooLarge => write!(f, "Message Too Large"),
+            SharedRingBufferError::PartialMessage => write!(f, "Partial Message")
+        }
+    }
+}
+
+// Why create or attach refused the shared memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutError {
+    // MEMORY_SIZE bytes are needed
+    MemoryTooSmall(u32),
+    Unaligned,
+    // no buffer of this kind was created there, or not yet
+    NotCreated,
+    Version(u32),
+    // what the buffer was created with
+    Geometry { slot_size: u32, slots: u32 },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::MemoryTooSmall(size) => write!(f, "memory too small, {} bytes needed", size),
+            LayoutError::Unaligned => write!(f, "memory not aligned to {} bytes", LINE),
+            LayoutError::NotCreated => write!(f, "no ring buffer created"),
+            LayoutError::Version(version) => write!(f, "layout version {}, expected {}", version, LAYOUT_VERSION),
+            LayoutError::Geometry { slot_size, slots } => write!(f, "created with {} slots of {} bytes", slots, slot_size),
+        }
+    }
+}
+
+// Bumped whenever the layout in shared memory changes.
+pub(crate) const LAYOUT_VERSION: u32 = 1;
+
+// Written by the core creating a buffer, checked by the one attaching to
+// it. The magic goes last, once everything else is in place.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Header {
+    magic: u32,
+    version: u32,
+    slot_size: u32,
+    slots: u32,
+    owner: u32,
+}
+
+impl Header {
+    pub(crate) fn new(slot_size: usize, slots: usize, owner: u32) -> Self {
+        Header { magic: 0, version: LAYOUT_VERSION, slot_size: slot_size as u32, slots: slots as u32, owner }
+    }
+
+    pub(crate) fn publish(&mut self, magic: u32) {
+        fence(Ordering::Release);
+        unsafe { write_volatile(&mut self.magic, magic) };
+    }
+
+    pub(crate) fn check(&self, magic: u32, slot_size: usize, slots: usize) -> Result<(), LayoutError> {
+        if unsafe { read_volatile(&self.magic) } != magic {
+            return Err(LayoutError::NotCreated);
+        }
+        fence(Ordering::Acquire);
+        if self.version != LAYOUT_VERSION {
+            return Err(LayoutError::Version(self.version));
+        }
+        if (self.slot_size, self.slots) != (slot_size as u32, slots as u32) {
+            return Err(LayoutError::Geometry { slot_size: self.slot_size, slots: self.slots });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn owner(&self) -> u32 {
+        self.owner
+    }
+}
+
+// Checks the memory handed to create or attach.
+pub(crate) fn check_memory(shared_address: *mut u32, buffer_size: u32, needed: u32) -> Result<(), LayoutError> {
+    if buffer_size < needed {
+        return Err(LayoutError::MemoryTooSmall(needed));
+    }
+    if shared_address.is_null() || !(shared_address as usize).is_multiple_of(LINE) {
+        return Err(LayoutError::Unaligned);
+    }
+    Ok(())
+}
+
+// Cache maintenance by address range for buffers in cacheable memory. The
+// core writing a slot cleans it, the one reading it invalidates it first.
+pub trait CacheCoherency {
+    // Slots and indices are aligned to 32 bytes, longer lines are refused
+    // at compile time.
+    const LINE_SIZE: usize;
+
+    fn clean(address: usize, len: usize);
+    fn invalidate(address: usize, len: usize);
+}
+
+// For the Cortex-M4 and for memory the Cortex-M7 maps non-cacheable.
+pub struct NoCacheMaintenance;
+
+impl CacheCoherency for NoCacheMaintenance {
+    const LINE_SIZE: usize = 1;
+
+    fn clean(_address: usize, _len: usize) {}
+    fn invalidate(_address: usize, _len: usize) {}
+}
+
+// The Cortex-M7 L1 data cache, through the maintenance registers of the SCB.
+pub struct CortexM7DCache;
+
+const DCIMVAC: *mut u32 = 0xe000_ef5c as *mut u32;
+const DCCMVAC: *mut u32 = 0xe000_ef68 as *mut u32;
+
+impl CortexM7DCache {
+    fn by_line(register: *mut u32, address: usize, len: usize) {
+        let start = address & !(Self::LINE_SIZE - 1);
+        barrier();
+        for line in (start..address + len).step_by(Self::LINE_SIZE) {
+            unsafe { write_volatile(register, line as u32) };
         }
+        barrier();
+    }
+}
+
+impl CacheCoherency for CortexM7DCache {
+    const LINE_SIZE: usize = 32;
+
+    fn clean(address: usize, len: usize) {
+        Self::by_line(DCCMVAC, address, len);
+    }
+
+    fn invalidate(address: usize, len: usize) {
+        Self::by_line(DCIMVAC, address, len);
     }
 }
 
+// dsb and isb, the maintenance has completed before the next access.
+#[inline(always)]
+fn barrier() {
+    #[cfg(target_arch = "arm")]
+    unsafe { core::arch::asm!("dsb", "isb", options(nostack, preserves_flags)) };
+}
+
+// Alignment of slots and indices, the cache line of the Cortex-M7.
+const LINE: usize = 32;
+
+// A word on a cache line of its own, as each index is written by one core
+// only.
+#[repr(C, align(32))]
+#[derive(Debug)]
+struct Line<T>(T);
+
 #[repr(C)]
 #[derive(Debug)]
 struct SharedRingBufferLayout<const S:usize,const N:usize> {
-    head: usize,
-    tail: usize,
-    start_of_frame: *mut [u8;S],
+    head: Line<usize>,
+    tail: Line<usize>,
+    header: Line<Header>,
+    start_of_frame: *mut Slot<S>,
+}
+
+impl<const S:usize,const N:usize> SharedRingBufferLayout<S,N> {
+    fn next(i: usize) -> usize {
+        if i + 1 >= N { 0 } else { i + 1 }
+    }
+
+    fn invalidate_indices<C: CacheCoherency>(&self) {
+        C::invalidate(&self.head as *const Line<usize> as usize, 2 * LINE);
+    }
+
+    fn clean_indices<C: CacheCoherency>(&self) {
+        C::clean(&self.head as *const Line<usize> as usize, 2 * LINE);
+    }
+
+    fn slot<C: CacheCoherency>(slots: &[Slot<S>], i: usize) -> &Slot<S> {
+        C::invalidate(&slots[i] as *const Slot<S> as usize, mem::size_of::<Slot<S>>());
+        &slots[i]
+    }
+
+    // Puts `message` in the slots from tail on, split over several of them
+    // if `fragment`. When full the oldest messages go if `overwrite`,
+    // otherwise nothing is written. The tail only moves once all of the
+    // message is in place, the reader never sees a part of it.
+    fn push<C: CacheCoherency>(&mut self, slots: &mut [Slot<S>], message: &[u8], fragment: bool, overwrite: bool) -> Result<(), SharedRingBufferError> {
+        let count = if fragment { message.len().div_ceil(S).max(1) } else { 1 };
+        if (!fragment && message.len() > S) || count >= N {
+            return Err(SharedRingBufferError::MessageTooLarge);
+        }
+        self.invalidate_indices::<C>();
+        let free = (self.head.0 + N - self.tail.0 - 1) % N;
+        if !overwrite && count > free {
+            return Err(SharedRingBufferError::NoSpace);
+        }
+        let mut tail = self.tail.0;
+        let mut chunks = message.chunks(S);
+        for i in 0..count {
+            if Self::next(tail) == self.head.0 {
+                // overwrite
+                self.head.0 = Self::next(self.head.0);
+            }
+            let mut flags = if i == 0 { FIRST } else { 0 };
+            if i + 1 < count { flags |= MORE };
+            slots[tail].put(chunks.next().unwrap_or(&[]), flags);
+            C::clean(&slots[tail] as *const Slot<S> as usize, mem::size_of::<Slot<S>>());
+            tail = Self::next(tail);
+        }
+        self.tail.0 = tail;
+        self.clean_indices::<C>();
+        Ok(())
+    }
+
+    fn pop<C: CacheCoherency>(&mut self, slots: &[Slot<S>], message: &mut [u8]) -> Result<usize, SharedRingBufferError> {
+        self.invalidate_indices::<C>();
+        if self.head.0 == self.tail.0 {
+            return Err(SharedRingBufferError::NoData);
+        }
+        let copy_size = Self::slot::<C>(slots, self.head.0).take(message);
+        self.head.0 = Self::next(self.head.0);
+        self.clean_indices::<C>();
+        Ok(copy_size)
+    }
+
+    // Takes a whole message, put together again from its slots. What is
+    // left of a message whose start was overwritten, or one missing its
+    // end, is dropped with PartialMessage.
+    fn pop_message<C: CacheCoherency>(&mut self, slots: &[Slot<S>], message: &mut [u8]) -> Result<usize, SharedRingBufferError> {
+        self.invalidate_indices::<C>();
+        let result = self.take_message::<C>(slots, message);
+        self.clean_indices::<C>();
+        result
+    }
+
+    fn take_message<C: CacheCoherency>(&mut self, slots: &[Slot<S>], message: &mut [u8]) -> Result<usize, SharedRingBufferError> {
+        let (head, tail) = (self.head.0, self.tail.0);
+        if head == tail {
+            return Err(SharedRingBufferError::NoData);
+        }
+        if Self::slot::<C>(slots, head).flags & FIRST == 0 {
+            while self.head.0 != tail && Self::slot::<C>(slots, self.head.0).flags & FIRST == 0 {
+                self.head.0 = Self::next(self.head.0);
+            }
+            return Err(SharedRingBufferError::PartialMessage);
+        }
+        let mut end = head;
+        let mut len = 0;
+        loop {
+            let slot = Self::slot::<C>(slots, end);
+            len += slot.len();
+            let more = slot.flags & MORE != 0;
+            end = Self::next(end);
+            if !more { break; }
+            if end == tail || Self::slot::<C>(slots, end).flags & FIRST != 0 {
+                self.head.0 = end;
+                return Err(SharedRingBufferError::PartialMessage);
+            }
+        }
+        if len > message.len() {
+            self.head.0 = end;
+            return Err(SharedRingBufferError::MessageTooLarge);
+        }
+        let mut copy_size = 0;
+        while self.head.0 != end {
+            copy_size += slots[self.head.0].take(&mut message[copy_size..]);
+            self.head.0 = Self::next(self.head.0);
+        }
+        Ok(copy_size)
+    }
+}
+
+// Slot flags. A message in one slot is FIRST, the slots of a fragmented one
+// all but the last MORE.
+pub(crate) const FIRST: u32 = 1;
+const MORE: u32 = 2;
+
+// One message or a fragment of one, `len` bytes of `data` are valid.
+// Aligned to a cache line so that maintaining one slot leaves the others
+// alone.
+#[repr(C, align(32))]
+pub(crate) struct Slot<const S:usize> {
+    len: u32,
+    flags: u32,
+    data: [u8;S],
+}
+
+impl<const S:usize> Slot<S> {
+    #[cfg(loom)]
+    pub(crate) const EMPTY: Self = Slot { len: 0, flags: 0, data: [0; S] };
+
+    pub(crate) fn put(&mut self, message: &[u8], flags: u32) {
+        self.data[..message.len()].copy_from_slice(message);
+        self.len = message.len() as u32;
+        self.flags = flags;
+    }
+
+    fn len(&self) -> usize {
+        // the peer may have left anything in shared memory
+        (self.len as usize).min(S)
+    }
+
+    // Copies the message into `message`, cut to its length if it is
+    // shorter.
+    pub(crate) fn take(&self, message: &mut [u8]) -> usize {
+        let len = self.len().min(message.len());
+        message[..len].copy_from_slice(&self.data[..len]);
+        len
+    }
+}
+
+// Bytes of shared memory a ring buffer of N messages of up to S bytes needs.
+const fn memory_size<const S:usize,const N:usize>() -> u32 {
+    (512 + N * mem::size_of::<Slot<S>>()) as u32
+}
+
+const fn check_line_size(line_size: usize) {
+    assert!(line_size <= LINE && LINE.is_multiple_of(line_size), "slots are aligned to 32 bytes, the cache line may not be longer");
+}
+
+const MAGIC: u32 = u32::from_le_bytes(*b"SRBF");
+
+type Parts<'a, const S:usize,const N:usize> = (&'a mut SharedRingBufferLayout<S, N>, &'a mut [Slot<S>]);
+
+// Lays out an empty ring buffer in the shared memory.
+unsafe fn create<'a, const S:usize,const N:usize, C: CacheCoherency>(shared_address: *mut u32, owner: u32) -> Parts<'a, S, N> {
+    ptr::write_bytes(shared_address as *mut u8, 0, memory_size::<S, N>() as usize);
+    let shared_ringbuffer = unsafe { (shared_address as *mut SharedRingBufferLayout<S, N>).as_mut().unwrap() };
+    shared_ringbuffer.start_of_frame = (shared_address as *mut u8).add(128) as *mut Slot<S>;
+    shared_ringbuffer.header.0 = Header::new(S, N, owner);
+    C::clean(shared_address as usize, memory_size::<S, N>() as usize);
+    shared_ringbuffer.header.0.publish(MAGIC);
+    C::clean(&shared_ringbuffer.header as *const Line<Header> as usize, LINE);
+    debug!("{:?}", shared_ringbuffer);
+    let shared_ringbuffer_holder = slice::from_raw_parts_mut(shared_ringbuffer.start_of_frame, N);
+    (shared_ringbuffer, shared_ringbuffer_holder)
+}
+
+// Finds the ring buffer the peer created in the shared memory.
+unsafe fn attach<'a, const S:usize,const N:usize, C: CacheCoherency>(shared_address: *mut u32) -> Result<Parts<'a, S, N>, LayoutError> {
+    let shared_ringbuffer = unsafe { (shared_address as *mut SharedRingBufferLayout<S, N>).as_mut().unwrap() };
+    C::invalidate(&shared_ringbuffer.header as *const Line<Header> as usize, LINE);
+    shared_ringbuffer.header.0.check(MAGIC, S, N)?;
+    debug!("{:?}", shared_ringbuffer);
+    let shared_ringbuffer_holder = slice::from_raw_parts_mut((shared_address as *mut u8).add(128) as *mut Slot<S>, N);
+    Ok((shared_ringbuffer, shared_ringbuffer_holder))
 }
 
-pub struct SharedRingBuffer<const S:usize,const N:usize>
+pub struct SharedRingBuffer<const S:usize,const N:usize, C = NoCacheMaintenance>
+where
+    C: CacheCoherency
 {
     shared_address  : *mut u32,
     buffer_size : u32,
     shared_ringbuffer: &'static mut SharedRingBufferLayout<S, N>,
-    shared_ringbuffer_holder: &'static mut [[u8;S]],
+    shared_ringbuffer_holder: &'static mut [Slot<S>],
+    fragment: bool,
+    cache: PhantomData<C>,
 }
 
-impl<const S:usize,const N:usize> SharedRingBuffer<S,N>
-{
+impl<const S:usize,const N:usize, C> SharedRingBuffer<S,N,C>
+where
+    C: CacheCoherency {
+    pub const MEMORY_SIZE: u32 = memory_size::<S, N>();
+    const LINE_SIZE_CHECK: () = check_line_size(C::LINE_SIZE);
+
+    /// Lays out an empty ring buffer, see [creating and attaching](self#creating-and-attaching).
+    ///
+    /// # Safety
+    ///
+    /// See [the module](self#safety).
+    pub unsafe fn create(shared_address: *mut u32,
+                         buffer_size: u32,
+                         owner: u32) -> Result<Self, LayoutError> {
+        let () = Self::LINE_SIZE_CHECK;
+        check_memory(shared_address, buffer_size, Self::MEMORY_SIZE)?;
+        let parts = create::<S, N, C>(shared_address, owner);
+        Ok(Self::new(shared_address, buffer_size, parts))
+    }
 
-    pub unsafe fn assign(shared_address: *mut u32,
-                         buffer_size: u32) -> Self {
-        let shared_ringbuffer_ptr = shared_address as *mut SharedRingBufferLayout<S, N>;
-        let shared_ringbuffer: &mut SharedRingBufferLayout<S, N> = unsafe { shared_ringbuffer_ptr.as_mut().unwrap() };
-        if (buffer_size as usize) < 512+S*N { panic!("memory size is not enough. memory size must be rather than {}", S*N+512); };
-        shared_ringbuffer.start_of_frame = (shared_address as *mut u8).add(128) as *mut [u8; S];
-        let shared_ringbuffer_holder = slice::from_raw_parts_mut(shared_ringbuffer.start_of_frame, N);
-        debug!("{:?}", shared_ringbuffer);
+    /// Uses the ring buffer the peer created, if it has the same geometry.
+    ///
+    /// # Safety
+    ///
+    /// As for [create](Self::create).
+    pub unsafe fn attach(shared_address: *mut u32,
+                         buffer_size: u32) -> Result<Self, LayoutError> {
+        let () = Self::LINE_SIZE_CHECK;
+        check_memory(shared_address, buffer_size, Self::MEMORY_SIZE)?;
+        let parts = attach::<S, N, C>(shared_address)?;
+        Ok(Self::new(shared_address, buffer_size, parts))
+    }
 
+    fn new(shared_address: *mut u32, buffer_size: u32, (shared_ringbuffer, shared_ringbuffer_holder): Parts<'static, S, N>) -> Self {
         SharedRingBuffer {
             shared_address,
             buffer_size,
             shared_ringbuffer,
-            shared_ringbuffer_holder
+            shared_ringbuffer_holder,
+            fragment: false,
+            cache: PhantomData,
         }
     }
 
+    // With fragmentation on, a message longer than S is split over several
+    // slots. Read it back with read_message.
+    pub fn set_fragmentation(&mut self, fragment: bool) {
+        self.fragment = fragment;
+    }
+
     pub fn write(&mut self, message : &[u8]) -> Result<(), SharedRingBufferError>{
         debug!("head {}, tail {}",
-               self.shared_ringbuffer.head,
-               self.shared_ringbuffer.tail);
-        let next_tail = if self.shared_ringbuffer.tail + 1 >= N { 0 } else { self.shared_ringbuffer.tail + 1 };
-        if next_tail == self.shared_ringbuffer.head {
-            return Err(SharedRingBufferError::NoSpace);
-        }
-        let dst_buffer = self.shared_ringbuffer_holder[self.shared_ringbuffer.tail].as_mut_slice();
-
-        dst_buffer[..message.len()].copy_from_slice(message);
-        self.shared_ringbuffer.tail = next_tail;
-        Ok(())
+               self.shared_ringbuffer.head.0,
+               self.shared_ringbuffer.tail.0);
+        self.shared_ringbuffer.push::<C>(self.shared_ringbuffer_holder, message, self.fragment, false)
     }
 
+    // Reads one slot, a fragment only if the writer fragments.
     pub fn read(&mut self, message : &mut [u8]) -> Result<usize, SharedRingBufferError> {
         debug!("head {}, tail {}",
-               self.shared_ringbuffer.head,
-               self.shared_ringbuffer.tail);
-        let head = self.shared_ringbuffer.head;
-        let tail = self.shared_ringbuffer.tail;
-
-        if head == tail {
-            return Err(SharedRingBufferError::NoData);
-        }
-
-        let src = self.shared_ringbuffer_holder[self.shared_ringbuffer.head].as_slice();
-        let dst_size = message.len();
-        let src_size = src.len();
-
-        let copy_size = if dst_size < src_size { dst_size } else { src_size };
-
-        message[..copy_size].copy_from_slice(&src[..copy_size]);
+               self.shared_ringbuffer.head.0,
+               self.shared_ringbuffer.tail.0);
+        self.shared_ringbuffer.pop::<C>(self.shared_ringbuffer_holder, message)
+    }
 
-        self.shared_ringbuffer.head += 1;
-        if self.shared_ringbuffer.head >= N { self.shared_ringbuffer.head = 0 };
+    pub fn read_message(&mut self, message : &mut [u8]) -> Result<usize, SharedRingBufferError> {
+        debug!("head {}, tail {}",
+               self.shared_ringbuffer.head.0,
+               self.shared_ringbuffer.tail.0);
+        self.shared_ringbuffer.pop_message::<C>(self.shared_ringbuffer_holder, message)
+    }
 
-        Ok(copy_size)
+    pub fn owner(&self) -> u32 {
+        self.shared_ringbuffer.header.0.owner()
     }
 
     pub fn size(&self) -> u32 {
@@ -106,84 +477,111 @@ pub trait CriticalSection {
     //fn free(&self, f:impl FnOnce()) -> Result<(),SharedRingBufferError>;
 }
 
-pub struct SharedRingBufferWithCS<const S:usize,const N:usize, CS>
+pub struct SharedRingBufferWithCS<const S:usize,const N:usize, CS, C = NoCacheMaintenance>
 where
-    CS: CriticalSection
+    CS: CriticalSection,
+    C: CacheCoherency
 {
     shared_address  : *mut u32,
     buffer_size : u32,
     cs: CS,
     shared_ringbuffer: &'static mut SharedRingBufferLayout<S, N>,
-    shared_ringbuffer_holder: &'static mut [[u8;S]],
+    shared_ringbuffer_holder: &'static mut [Slot<S>],
+    fragment: bool,
+    cache: PhantomData<C>,
 }
 
-impl<const S:usize,const N:usize, CS> SharedRingBufferWithCS<S,N,CS>
+impl<const S:usize,const N:usize, CS, C> SharedRingBufferWithCS<S,N,CS,C>
 where
-    CS: CriticalSection {
+    CS: CriticalSection,
+    C: CacheCoherency {
+    pub const MEMORY_SIZE: u32 = memory_size::<S, N>();
+    const LINE_SIZE_CHECK: () = check_line_size(C::LINE_SIZE);
+
+    /// Lays out an empty ring buffer, see [creating and attaching](self#creating-and-attaching).
+    ///
+    /// # Safety
+    ///
+    /// See [the module](self#safety). Both writer and reader move the
+    /// indices, the peer must take the same lock as `cs`.
+    pub unsafe fn create(shared_address: *mut u32,
+                         buffer_size: u32,
+                         owner: u32,
+                         cs: CS) -> Result<Self, LayoutError> {
+        let () = Self::LINE_SIZE_CHECK;
+        check_memory(shared_address, buffer_size, Self::MEMORY_SIZE)?;
+        let parts = create::<S, N, C>(shared_address, owner);
+        Ok(Self::new(shared_address, buffer_size, cs, parts))
+    }
 
-    pub unsafe fn assign(shared_address: *mut u32,
+    /// Uses the ring buffer the peer created, if it has the same geometry.
+    ///
+    /// # Safety
+    ///
+    /// As for [create](Self::create).
+    pub unsafe fn attach(shared_address: *mut u32,
                          buffer_size: u32,
-                         cs: CS) -> Self {
-        let shared_ringbuffer_ptr = shared_address as *mut SharedRingBufferLayout<S, N>;
-        let shared_ringbuffer: &mut SharedRingBufferLayout<S, N> = unsafe { shared_ringbuffer_ptr.as_mut().unwrap() };
-        if (buffer_size as usize) < 512+S*N { panic!("memory size is not enough. memory size must be rather than {}", S*N+512); };
-        shared_ringbuffer.start_of_frame = (shared_address as *mut u8).add(128) as *mut [u8; S];
-        let shared_ringbuffer_holder = slice::from_raw_parts_mut(shared_ringbuffer.start_of_frame, N);
-        debug!("{:?}", shared_ringbuffer);
+                         cs: CS) -> Result<Self, LayoutError> {
+        let () = Self::LINE_SIZE_CHECK;
+        check_memory(shared_address, buffer_size, Self::MEMORY_SIZE)?;
+        let parts = attach::<S, N, C>(shared_address)?;
+        Ok(Self::new(shared_address, buffer_size, cs, parts))
+    }
 
+    fn new(shared_address: *mut u32, buffer_size: u32, cs: CS, (shared_ringbuffer, shared_ringbuffer_holder): Parts<'static, S, N>) -> Self {
         SharedRingBufferWithCS {
             shared_address,
             buffer_size,
             cs,
             shared_ringbuffer,
-            shared_ringbuffer_holder
+            shared_ringbuffer_holder,
+            fragment: false,
+            cache: PhantomData,
         }
     }
 
+    // With fragmentation on, a message longer than S is split over several
+    // slots. Read it back with read_message.
+    pub fn set_fragmentation(&mut self, fragment: bool) {
+        self.fragment = fragment;
+    }
+
     pub fn write(&mut self, message : &[u8]) -> Result<(), SharedRingBufferError>{
         if let Ok(_l) = self.cs.lock() {
             debug!("head {}, tail {}",
-                   self.shared_ringbuffer.head,
-                   self.shared_ringbuffer.tail);
-            let next_tail = if self.shared_ringbuffer.tail + 1 >= N { 0 } else { self.shared_ringbuffer.tail + 1 };
-            if self.shared_ringbuffer.head == next_tail {
-                // overwrite
-                let next_head = if self.shared_ringbuffer.head + 1 >= N { 0 } else { self.shared_ringbuffer.head + 1 };
-                self.shared_ringbuffer.head = next_head;
-            }
-            let dst_buffer = self.shared_ringbuffer_holder[self.shared_ringbuffer.tail].as_mut_slice();
-            dst_buffer[..message.len()].copy_from_slice(message);
-            self.shared_ringbuffer.tail = next_tail;
-            Ok(())
+                   self.shared_ringbuffer.head.0,
+                   self.shared_ringbuffer.tail.0);
+            self.shared_ringbuffer.push::<C>(self.shared_ringbuffer_holder, message, self.fragment, true)
         } else {
             Err(SharedRingBufferError::CantLock)
         }
     }
 
+    // Reads one slot, a fragment only if the writer fragments.
     pub fn read(&mut self, message : &mut [u8]) -> Result<usize, SharedRingBufferError> {
+        if let Ok(_l) = self.cs.lock() {
+            debug!("head {}, tail {}",
+                   self.shared_ringbuffer.head.0,
+                   self.shared_ringbuffer.tail.0);
+            self.shared_ringbuffer.pop::<C>(self.shared_ringbuffer_holder, message)
+        } else {
+            Err(SharedRingBufferError::CantLock)
+        }
+    }
 
-        let _l = self.cs.lock();
-        debug!("head {}, tail {}",
-               self.shared_ringbuffer.head,
-               self.shared_ringbuffer.tail);
-
-        let head = self.shared_ringbuffer.head;
-        let tail = self.shared_ringbuffer.tail;
-
-        if head == tail { return Err(SharedRingBufferError::NoData) };
-
-        let src = self.shared_ringbuffer_holder[self.shared_ringbuffer.head].as_slice();
-        let dst_size = message.len();
-        let src_size = src.len();
-
-        let copy_size = if dst_size < src_size { dst_size } else { src_size };
-
-        message[..copy_size].copy_from_slice(&src[..copy_size]);
-
-        self.shared_ringbuffer.head += 1;
-        if self.shared_ringbuffer.head >= N { self.shared_ringbuffer.head = 0 };
+    pub fn read_message(&mut self, message : &mut [u8]) -> Result<usize, SharedRingBufferError> {
+        if let Ok(_l) = self.cs.lock() {
+            debug!("head {}, tail {}",
+                   self.shared_ringbuffer.head.0,
+                   self.shared_ringbuffer.tail.0);
+            self.shared_ringbuffer.pop_message::<C>(self.shared_ringbuffer_holder, message)
+        } else {
+            Err(SharedRingBufferError::CantLock)
+        }
+    }
 
-        Ok(copy_size)
+    pub fn owner(&self) -> u32 {
+        self.shared_ringbuffer.header.0.owner()
     }
 
     pub fn size(&self) -> u32 {
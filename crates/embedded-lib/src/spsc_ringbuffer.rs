@@ -0,0 +1,172 @@
+// Lock-free ring buffer between one writing and one reading core.
+//
+// Only the writer moves the tail and only the reader the head, so unlike
+// SharedRingBufferWithCS no hardware semaphore is needed. The writer
+// publishes a slot with a Release store of the tail once it is filled, the
+// reader gives it back with a Release store of the head once it is copied
+// out. Check the ordering with
+//
+//   RUSTFLAGS="--cfg loom" cargo test --release --test loom
+
+use core::{mem, ptr};
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(loom)]
+use loom::{cell::UnsafeCell, sync::atomic::{AtomicUsize, Ordering}};
+use log::debug;
+
+use crate::shared_ringbuffer::{check_memory, Header, LayoutError, Slot, SharedRingBufferError, FIRST};
+
+// Same interface as loom's UnsafeCell, which tracks the slot accesses.
+#[cfg(not(loom))]
+#[repr(transparent)]
+struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+// Orders the slot accesses against the index update seen by the other
+// core. The Acquire/Release accesses imply it, but the peer is the other
+// Cortex-M on the bus matrix rather than a thread, so it is not left to
+// how they are lowered.
+#[inline(always)]
+fn dmb() {
+    #[cfg(all(target_arch = "arm", not(loom)))]
+    unsafe { core::arch::asm!("dmb", options(nostack, preserves_flags)) };
+}
+
+#[repr(C)]
+struct SpscLayout<const S:usize,const N:usize> {
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    header: Header,
+    slots: [UnsafeCell<Slot<S>>; N],
+}
+
+const MAGIC: u32 = u32::from_le_bytes(*b"SPSC");
+
+pub struct SpscSharedRingBuffer<const S:usize,const N:usize>
+{
+    shared_address  : *mut u32,
+    buffer_size : u32,
+    ring: &'static SpscLayout<S, N>,
+}
+
+// Each core holds one end, the writer or the reader.
+unsafe impl<const S:usize,const N:usize> Send for SpscSharedRingBuffer<S,N> {}
+
+impl<const S:usize,const N:usize> SpscSharedRingBuffer<S,N>
+{
+    pub const MEMORY_SIZE: u32 = mem::size_of::<SpscLayout<S, N>>() as u32;
+
+    /// Lays out an empty ring buffer, see [creating and attaching](crate::shared_ringbuffer#creating-and-attaching).
+    ///
+    /// # Safety
+    ///
+    /// See [`shared_ringbuffer`](crate::shared_ringbuffer#safety). One core
+    /// may only write, the other only read.
+    pub unsafe fn create(shared_address: *mut u32,
+                         buffer_size: u32,
+                         owner: u32) -> Result<Self, LayoutError> {
+        check_memory(shared_address, buffer_size, Self::MEMORY_SIZE)?;
+        let layout = shared_address as *mut SpscLayout<S, N>;
+        ptr::write_bytes(layout, 0, 1);
+        (*layout).header = Header::new(S, N, owner);
+        (*layout).header.publish(MAGIC);
+        Ok(Self::new(shared_address, buffer_size))
+    }
+
+    /// Uses the ring buffer the peer created, if it has the same geometry.
+    ///
+    /// # Safety
+    ///
+    /// As for [create](Self::create).
+    pub unsafe fn attach(shared_address: *mut u32,
+                         buffer_size: u32) -> Result<Self, LayoutError> {
+        check_memory(shared_address, buffer_size, Self::MEMORY_SIZE)?;
+        (*(shared_address as *const SpscLayout<S, N>)).header.check(MAGIC, S, N)?;
+        Ok(Self::new(shared_address, buffer_size))
+    }
+
+    unsafe fn new(shared_address: *mut u32, buffer_size: u32) -> Self {
+        SpscSharedRingBuffer {
+            shared_address,
+            buffer_size,
+            ring: unsafe { &*(shared_address as *const SpscLayout<S, N>) },
+        }
+    }
+
+    // Writer and reader of a ring buffer on the heap, for loom.
+    #[cfg(loom)]
+    pub fn pair() -> (Self, Self) {
+        let ring = std::boxed::Box::leak(std::boxed::Box::new(SpscLayout {
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            header: Header::new(S, N, 0),
+            slots: core::array::from_fn(|_| UnsafeCell::new(Slot::EMPTY)),
+        }));
+        let shared_address = ring as *mut SpscLayout<S, N> as *mut u32;
+        let end = || SpscSharedRingBuffer { shared_address, buffer_size: Self::MEMORY_SIZE, ring };
+        (end(), end())
+    }
+
+    fn next(i: usize) -> usize {
+        if i + 1 >= N { 0 } else { i + 1 }
+    }
+
+    pub fn write(&mut self, message : &[u8]) -> Result<(), SharedRingBufferError>{
+        if message.len() > S {
+            return Err(SharedRingBufferError::MessageTooLarge);
+        }
+        // only this side moves the tail
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        debug!("head {}, tail {}", head, tail);
+        let next_tail = Self::next(tail);
+        if next_tail == head {
+            return Err(SharedRingBufferError::NoSpace);
+        }
+        // the reader is done with the slot
+        dmb();
+        self.ring.slots[tail].with_mut(|slot| unsafe { (*slot).put(message, FIRST) });
+        dmb();
+        self.ring.tail.store(next_tail, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn read(&mut self, message : &mut [u8]) -> Result<usize, SharedRingBufferError> {
+        // only this side moves the head
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        debug!("head {}, tail {}", head, tail);
+        if head == tail {
+            return Err(SharedRingBufferError::NoData);
+        }
+        // the writer is done with the slot
+        dmb();
+        let copy_size = self.ring.slots[head].with(|slot| unsafe { (*slot).take(message) });
+        dmb();
+        self.ring.head.store(Self::next(head), Ordering::Release);
+        Ok(copy_size)
+    }
+
+    pub fn owner(&self) -> u32 {
+        self.ring.header.owner()
+    }
+
+    pub fn size(&self) -> u32 {
+        self.buffer_size
+    }
+
+    pub fn as_ptr(&self) -> *mut u32 {
+        self.shared_address
+    }
+}
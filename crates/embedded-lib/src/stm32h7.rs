@@ -0,0 +1,49 @@
+// Memory map of the STM32H745/755 for the memory commands. The register
+// table is generated, see tools/svd2regs.py.
+
+use crate::memory::{Peripheral, Region};
+
+mod registers;
+
+pub use registers::PERIPHERALS;
+
+const fn region(name: &'static str, start: usize, size: usize, writable: bool) -> Region {
+    Region { name, start, size, writable }
+}
+
+const FLASH: Region = region("FLASH", 0x0800_0000, 0x20_0000, false);
+const AXI_SRAM: Region = region("AXI_SRAM", 0x2400_0000, 0x8_0000, true);
+const SRAM1: Region = region("SRAM1", 0x3000_0000, 0x2_0000, true);
+const SRAM2: Region = region("SRAM2", 0x3002_0000, 0x2_0000, true);
+const SRAM3: Region = region("SRAM3", 0x3004_0000, 0x8000, true);
+const SRAM4: Region = region("SRAM4", 0x3800_0000, 0x1_0000, true);
+const BACKUP_SRAM: Region = region("BACKUP_SRAM", 0x3880_0000, 0x1000, true);
+
+// The tightly coupled memories are only seen by the Cortex-M7.
+pub static CM7_REGIONS: [Region; 9] = [
+    region("ITCM", 0x0000_0000, 0x1_0000, true),
+    FLASH,
+    region("DTCM", 0x2000_0000, 0x2_0000, true),
+    AXI_SRAM,
+    SRAM1,
+    SRAM2,
+    SRAM3,
+    SRAM4,
+    BACKUP_SRAM,
+];
+
+// SRAM1 to SRAM3 are also mapped at 0x1000_0000 for the Cortex-M4.
+pub static CM4_REGIONS: [Region; 8] = [
+    FLASH,
+    region("SRAM_D2_ALIAS", 0x1000_0000, 0x4_8000, true),
+    AXI_SRAM,
+    SRAM1,
+    SRAM2,
+    SRAM3,
+    SRAM4,
+    BACKUP_SRAM,
+];
+
+pub fn peripheral(name: &str) -> Option<&'static Peripheral> {
+    PERIPHERALS.iter().find(|peripheral| peripheral.name.eq_ignore_ascii_case(name))
+}
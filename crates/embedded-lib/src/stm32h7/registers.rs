@@ -0,0 +1,270 @@
+// Generated by tools/svd2regs.py from STM32H755_CM7_subset.svd, do not edit.
+
+use crate::memory::{Access::*, Peripheral, Register};
+
+static HSEM_REGISTERS: [Register; 74] = [
+    Register::new("R0", 0x000, ReadWrite),
+    Register::new("R1", 0x004, ReadWrite),
+    Register::new("R2", 0x008, ReadWrite),
+    Register::new("R3", 0x00c, ReadWrite),
+    Register::new("R4", 0x010, ReadWrite),
+    Register::new("R5", 0x014, ReadWrite),
+    Register::new("R6", 0x018, ReadWrite),
+    Register::new("R7", 0x01c, ReadWrite),
+    Register::new("R8", 0x020, ReadWrite),
+    Register::new("R9", 0x024, ReadWrite),
+    Register::new("R10", 0x028, ReadWrite),
+    Register::new("R11", 0x02c, ReadWrite),
+    Register::new("R12", 0x030, ReadWrite),
+    Register::new("R13", 0x034, ReadWrite),
+    Register::new("R14", 0x038, ReadWrite),
+    Register::new("R15", 0x03c, ReadWrite),
+    Register::new("R16", 0x040, ReadWrite),
+    Register::new("R17", 0x044, ReadWrite),
+    Register::new("R18", 0x048, ReadWrite),
+    Register::new("R19", 0x04c, ReadWrite),
+    Register::new("R20", 0x050, ReadWrite),
+    Register::new("R21", 0x054, ReadWrite),
+    Register::new("R22", 0x058, ReadWrite),
+    Register::new("R23", 0x05c, ReadWrite),
+    Register::new("R24", 0x060, ReadWrite),
+    Register::new("R25", 0x064, ReadWrite),
+    Register::new("R26", 0x068, ReadWrite),
+    Register::new("R27", 0x06c, ReadWrite),
+    Register::new("R28", 0x070, ReadWrite),
+    Register::new("R29", 0x074, ReadWrite),
+    Register::new("R30", 0x078, ReadWrite),
+    Register::new("R31", 0x07c, ReadWrite),
+    Register::new("RLR0", 0x080, ReadSideEffect),
+    Register::new("RLR1", 0x084, ReadSideEffect),
+    Register::new("RLR2", 0x088, ReadSideEffect),
+    Register::new("RLR3", 0x08c, ReadSideEffect),
+    Register::new("RLR4", 0x090, ReadSideEffect),
+    Register::new("RLR5", 0x094, ReadSideEffect),
+    Register::new("RLR6", 0x098, ReadSideEffect),
+    Register::new("RLR7", 0x09c, ReadSideEffect),
+    Register::new("RLR8", 0x0a0, ReadSideEffect),
+    Register::new("RLR9", 0x0a4, ReadSideEffect),
+    Register::new("RLR10", 0x0a8, ReadSideEffect),
+    Register::new("RLR11", 0x0ac, ReadSideEffect),
+    Register::new("RLR12", 0x0b0, ReadSideEffect),
+    Register::new("RLR13", 0x0b4, ReadSideEffect),
+    Register::new("RLR14", 0x0b8, ReadSideEffect),
+    Register::new("RLR15", 0x0bc, ReadSideEffect),
+    Register::new("RLR16", 0x0c0, ReadSideEffect),
+    Register::new("RLR17", 0x0c4, ReadSideEffect),
+    Register::new("RLR18", 0x0c8, ReadSideEffect),
+    Register::new("RLR19", 0x0cc, ReadSideEffect),
+    Register::new("RLR20", 0x0d0, ReadSideEffect),
+    Register::new("RLR21", 0x0d4, ReadSideEffect),
+    Register::new("RLR22", 0x0d8, ReadSideEffect),
+    Register::new("RLR23", 0x0dc, ReadSideEffect),
+    Register::new("RLR24", 0x0e0, ReadSideEffect),
+    Register::new("RLR25", 0x0e4, ReadSideEffect),
+    Register::new("RLR26", 0x0e8, ReadSideEffect),
+    Register::new("RLR27", 0x0ec, ReadSideEffect),
+    Register::new("RLR28", 0x0f0, ReadSideEffect),
+    Register::new("RLR29", 0x0f4, ReadSideEffect),
+    Register::new("RLR30", 0x0f8, ReadSideEffect),
+    Register::new("RLR31", 0x0fc, ReadSideEffect),
+    Register::new("C1IER", 0x100, ReadWrite),
+    Register::new("C1ICR", 0x104, ReadWrite),
+    Register::new("C1ISR", 0x108, ReadOnly),
+    Register::new("C1MISR", 0x10c, ReadOnly),
+    Register::new("C2IER", 0x110, ReadWrite),
+    Register::new("C2ICR", 0x114, ReadWrite),
+    Register::new("C2ISR", 0x118, ReadOnly),
+    Register::new("C2MISR", 0x11c, ReadOnly),
+    Register::new("CR", 0x140, WriteOnly),
+    Register::new("KEYR", 0x144, ReadWrite),
+];
+
+static RCC_REGISTERS: [Register; 93] = [
+    Register::new("CR", 0x000, ReadWrite),
+    Register::new("HSICFGR", 0x004, ReadWrite),
+    Register::new("CRRCR", 0x008, ReadOnly),
+    Register::new("CSICFGR", 0x00c, ReadWrite),
+    Register::new("CFGR", 0x010, ReadWrite),
+    Register::new("D1CFGR", 0x018, ReadWrite),
+    Register::new("D2CFGR", 0x01c, ReadWrite),
+    Register::new("D3CFGR", 0x020, ReadWrite),
+    Register::new("PLLCKSELR", 0x028, ReadWrite),
+    Register::new("PLLCFGR", 0x02c, ReadWrite),
+    Register::new("PLL1DIVR", 0x030, ReadWrite),
+    Register::new("PLL1FRACR", 0x034, ReadWrite),
+    Register::new("PLL2DIVR", 0x038, ReadWrite),
+    Register::new("PLL2FRACR", 0x03c, ReadWrite),
+    Register::new("PLL3DIVR", 0x040, ReadWrite),
+    Register::new("PLL3FRACR", 0x044, ReadWrite),
+    Register::new("D1CCIPR", 0x04c, ReadWrite),
+    Register::new("D2CCIP1R", 0x050, ReadWrite),
+    Register::new("D2CCIP2R", 0x054, ReadWrite),
+    Register::new("D3CCIPR", 0x058, ReadWrite),
+    Register::new("CIER", 0x060, ReadWrite),
+    Register::new("CIFR", 0x064, ReadOnly),
+    Register::new("CICR", 0x068, ReadWrite),
+    Register::new("BDCR", 0x070, ReadWrite),
+    Register::new("CSR", 0x074, ReadWrite),
+    Register::new("AHB3RSTR", 0x07c, ReadWrite),
+    Register::new("AHB1RSTR", 0x080, ReadWrite),
+    Register::new("AHB2RSTR", 0x084, ReadWrite),
+    Register::new("AHB4RSTR", 0x088, ReadWrite),
+    Register::new("APB3RSTR", 0x08c, ReadWrite),
+    Register::new("APB1LRSTR", 0x090, ReadWrite),
+    Register::new("APB1HRSTR", 0x094, ReadWrite),
+    Register::new("APB2RSTR", 0x098, ReadWrite),
+    Register::new("APB4RSTR", 0x09c, ReadWrite),
+    Register::new("GCR", 0x0a0, ReadWrite),
+    Register::new("D3AMR", 0x0a8, ReadWrite),
+    Register::new("RSR", 0x0d0, ReadWrite),
+    Register::new("AHB3ENR", 0x0d4, ReadWrite),
+    Register::new("AHB1ENR", 0x0d8, ReadWrite),
+    Register::new("AHB2ENR", 0x0dc, ReadWrite),
+    Register::new("AHB4ENR", 0x0e0, ReadWrite),
+    Register::new("APB3ENR", 0x0e4, ReadWrite),
+    Register::new("APB1LENR", 0x0e8, ReadWrite),
+    Register::new("APB1HENR", 0x0ec, ReadWrite),
+    Register::new("APB2ENR", 0x0f0, ReadWrite),
+    Register::new("APB4ENR", 0x0f4, ReadWrite),
+    Register::new("AHB3LPENR", 0x0fc, ReadWrite),
+    Register::new("AHB1LPENR", 0x100, ReadWrite),
+    Register::new("AHB2LPENR", 0x104, ReadWrite),
+    Register::new("AHB4LPENR", 0x108, ReadWrite),
+    Register::new("APB3LPENR", 0x10c, ReadWrite),
+    Register::new("APB1LLPENR", 0x110, ReadWrite),
+    Register::new("APB1HLPENR", 0x114, ReadWrite),
+    Register::new("APB2LPENR", 0x118, ReadWrite),
+    Register::new("APB4LPENR", 0x11c, ReadWrite),
+    Register::new("C1_RSR", 0x130, ReadWrite),
+    Register::new("C1_AHB3ENR", 0x134, ReadWrite),
+    Register::new("C1_AHB1ENR", 0x138, ReadWrite),
+    Register::new("C1_AHB2ENR", 0x13c, ReadWrite),
+    Register::new("C1_AHB4ENR", 0x140, ReadWrite),
+    Register::new("C1_APB3ENR", 0x144, ReadWrite),
+    Register::new("C1_APB1LENR", 0x148, ReadWrite),
+    Register::new("C1_APB1HENR", 0x14c, ReadWrite),
+    Register::new("C1_APB2ENR", 0x150, ReadWrite),
+    Register::new("C1_APB4ENR", 0x154, ReadWrite),
+    Register::new("C1_AHB3LPENR", 0x15c, ReadWrite),
+    Register::new("C1_AHB1LPENR", 0x160, ReadWrite),
+    Register::new("C1_AHB2LPENR", 0x164, ReadWrite),
+    Register::new("C1_AHB4LPENR", 0x168, ReadWrite),
+    Register::new("C1_APB3LPENR", 0x16c, ReadWrite),
+    Register::new("C1_APB1LLPENR", 0x170, ReadWrite),
+    Register::new("C1_APB1HLPENR", 0x174, ReadWrite),
+    Register::new("C1_APB2LPENR", 0x178, ReadWrite),
+    Register::new("C1_APB4LPENR", 0x17c, ReadWrite),
+    Register::new("C2_RSR", 0x190, ReadWrite),
+    Register::new("C2_AHB3ENR", 0x194, ReadWrite),
+    Register::new("C2_AHB1ENR", 0x198, ReadWrite),
+    Register::new("C2_AHB2ENR", 0x19c, ReadWrite),
+    Register::new("C2_AHB4ENR", 0x1a0, ReadWrite),
+    Register::new("C2_APB3ENR", 0x1a4, ReadWrite),
+    Register::new("C2_APB1LENR", 0x1a8, ReadWrite),
+    Register::new("C2_APB1HENR", 0x1ac, ReadWrite),
+    Register::new("C2_APB2ENR", 0x1b0, ReadWrite),
+    Register::new("C2_APB4ENR", 0x1b4, ReadWrite),
+    Register::new("C2_AHB3LPENR", 0x1bc, ReadWrite),
+    Register::new("C2_AHB1LPENR", 0x1c0, ReadWrite),
+    Register::new("C2_AHB2LPENR", 0x1c4, ReadWrite),
+    Register::new("C2_AHB4LPENR", 0x1c8, ReadWrite),
+    Register::new("C2_APB3LPENR", 0x1cc, ReadWrite),
+    Register::new("C2_APB1LLPENR", 0x1d0, ReadWrite),
+    Register::new("C2_APB1HLPENR", 0x1d4, ReadWrite),
+    Register::new("C2_APB2LPENR", 0x1d8, ReadWrite),
+    Register::new("C2_APB4LPENR", 0x1dc, ReadWrite),
+];
+
+static EXTI_REGISTERS: [Register; 35] = [
+    Register::new("RTSR1", 0x000, ReadWrite),
+    Register::new("FTSR1", 0x004, ReadWrite),
+    Register::new("SWIER1", 0x008, ReadWrite),
+    Register::new("D3PMR1", 0x00c, ReadWrite),
+    Register::new("D3PCR1L", 0x010, ReadWrite),
+    Register::new("D3PCR1H", 0x014, ReadWrite),
+    Register::new("RTSR2", 0x020, ReadWrite),
+    Register::new("FTSR2", 0x024, ReadWrite),
+    Register::new("SWIER2", 0x028, ReadWrite),
+    Register::new("D3PMR2", 0x02c, ReadWrite),
+    Register::new("D3PCR2L", 0x030, ReadWrite),
+    Register::new("D3PCR2H", 0x034, ReadWrite),
+    Register::new("RTSR3", 0x040, ReadWrite),
+    Register::new("FTSR3", 0x044, ReadWrite),
+    Register::new("SWIER3", 0x048, ReadWrite),
+    Register::new("D3PMR3", 0x04c, ReadWrite),
+    Register::new("D3PCR3H", 0x054, ReadWrite),
+    Register::new("C1IMR1", 0x080, ReadWrite),
+    Register::new("C1EMR1", 0x084, ReadWrite),
+    Register::new("C1PR1", 0x088, ReadWrite),
+    Register::new("C1IMR2", 0x090, ReadWrite),
+    Register::new("C1EMR2", 0x094, ReadWrite),
+    Register::new("C1PR2", 0x098, ReadWrite),
+    Register::new("C1IMR3", 0x0a0, ReadWrite),
+    Register::new("C1EMR3", 0x0a4, ReadWrite),
+    Register::new("C1PR3", 0x0a8, ReadWrite),
+    Register::new("C2IMR1", 0x0c0, ReadWrite),
+    Register::new("C2EMR1", 0x0c4, ReadWrite),
+    Register::new("C2PR1", 0x0c8, ReadWrite),
+    Register::new("C2IMR2", 0x0d0, ReadWrite),
+    Register::new("C2EMR2", 0x0d4, ReadWrite),
+    Register::new("C2PR2", 0x0d8, ReadWrite),
+    Register::new("C2IMR3", 0x0e0, ReadWrite),
+    Register::new("C2EMR3", 0x0e4, ReadWrite),
+    Register::new("C2PR3", 0x0e8, ReadWrite),
+];
+
+static TIM2_REGISTERS: [Register; 20] = [
+    Register::new("CR1", 0x000, ReadWrite),
+    Register::new("CR2", 0x004, ReadWrite),
+    Register::new("SMCR", 0x008, ReadWrite),
+    Register::new("DIER", 0x00c, ReadWrite),
+    Register::new("SR", 0x010, ReadWrite),
+    Register::new("EGR", 0x014, WriteOnly),
+    Register::new("CCMR1_Output", 0x018, ReadWrite),
+    Register::new("CCMR2_Output", 0x01c, ReadWrite),
+    Register::new("CCER", 0x020, ReadWrite),
+    Register::new("CNT", 0x024, ReadWrite),
+    Register::new("PSC", 0x028, ReadWrite),
+    Register::new("ARR", 0x02c, ReadWrite),
+    Register::new("CCR1", 0x034, ReadWrite),
+    Register::new("CCR2", 0x038, ReadWrite),
+    Register::new("CCR3", 0x03c, ReadWrite),
+    Register::new("CCR4", 0x040, ReadWrite),
+    Register::new("DCR", 0x048, ReadWrite),
+    Register::new("DMAR", 0x04c, ReadSideEffect),
+    Register::new("AF1", 0x060, ReadWrite),
+    Register::new("TISEL", 0x068, ReadWrite),
+];
+
+static USART1_REGISTERS: [Register; 12] = [
+    Register::new("CR1", 0x000, ReadWrite),
+    Register::new("CR2", 0x004, ReadWrite),
+    Register::new("CR3", 0x008, ReadWrite),
+    Register::new("BRR", 0x00c, ReadWrite),
+    Register::new("GTPR", 0x010, ReadWrite),
+    Register::new("RTOR", 0x014, ReadWrite),
+    Register::new("RQR", 0x018, WriteOnly),
+    Register::new("ISR", 0x01c, ReadOnly),
+    Register::new("ICR", 0x020, WriteOnly),
+    Register::new("RDR", 0x024, ReadSideEffect),
+    Register::new("TDR", 0x028, ReadWrite),
+    Register::new("PRESC", 0x02c, ReadWrite),
+];
+
+pub static PERIPHERALS: [Peripheral; 15] = [
+    Peripheral { name: "HSEM", base: 0x5802_6400, size: 0x400, registers: &HSEM_REGISTERS },
+    Peripheral { name: "RCC", base: 0x5802_4400, size: 0x400, registers: &RCC_REGISTERS },
+    Peripheral { name: "EXTI", base: 0x5800_0000, size: 0x400, registers: &EXTI_REGISTERS },
+    Peripheral { name: "TIM2", base: 0x4000_0000, size: 0x400, registers: &TIM2_REGISTERS },
+    Peripheral { name: "TIM3", base: 0x4000_0400, size: 0x400, registers: &TIM2_REGISTERS },
+    Peripheral { name: "TIM4", base: 0x4000_0800, size: 0x400, registers: &TIM2_REGISTERS },
+    Peripheral { name: "TIM5", base: 0x4000_0c00, size: 0x400, registers: &TIM2_REGISTERS },
+    Peripheral { name: "USART1", base: 0x4001_1000, size: 0x400, registers: &USART1_REGISTERS },
+    Peripheral { name: "USART2", base: 0x4000_4400, size: 0x400, registers: &USART1_REGISTERS },
+    Peripheral { name: "USART3", base: 0x4000_4800, size: 0x400, registers: &USART1_REGISTERS },
+    Peripheral { name: "UART4", base: 0x4000_4c00, size: 0x400, registers: &USART1_REGISTERS },
+    Peripheral { name: "UART5", base: 0x4000_5000, size: 0x400, registers: &USART1_REGISTERS },
+    Peripheral { name: "USART6", base: 0x4001_1400, size: 0x400, registers: &USART1_REGISTERS },
+    Peripheral { name: "UART7", base: 0x4000_7800, size: 0x400, registers: &USART1_REGISTERS },
+    Peripheral { name: "UART8", base: 0x4000_7c00, size: 0x400, registers: &USART1_REGISTERS },
+];
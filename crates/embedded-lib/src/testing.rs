@@ -0,0 +1,349 @@
+// Host side harness for Console tests.
+//
+// Keystrokes are fed through a virtual transport, the bytes the console
+// sends back are run through a small VT100 screen model so that tests can
+// check what the operator would see.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::console::{Console, InputHandler, Output};
+use crate::transport::Transport;
+use crate::unicode::char_width;
+
+const ESC:char = '\x1b';
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Char(char),
+    // right half of a wide character
+    Continuation,
+}
+
+pub struct Screen {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Vec<Cell>>,
+    row: usize,
+    col: usize,
+    // the last column has been written, the next character goes to the
+    // next row
+    wrap_pending: bool,
+    saved: (usize, usize, bool),
+    bells: usize,
+    // answers to ESC [ 6 n
+    replies: Vec<u8>,
+    pending: Vec<u8>,
+    sequence: Option<String>,
+}
+
+impl Screen {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Screen {
+            rows,
+            cols,
+            cells: vec![vec![Cell::Char(' '); cols]; rows],
+            row: 0,
+            col: 0,
+            wrap_pending: false,
+            saved: (0, 0, false),
+            bells: 0,
+            replies: Vec::new(),
+            pending: Vec::new(),
+            sequence: None,
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    pub fn bells(&self) -> usize {
+        self.bells
+    }
+
+    // Text of a row without trailing blanks.
+    pub fn row_text(&self, row: usize) -> String {
+        let text: String = self.cells[row].iter().filter_map(|cell| match cell {
+            Cell::Char(c) => Some(*c),
+            Cell::Continuation => None,
+        }).collect();
+        String::from(text.trim_end())
+    }
+
+    pub fn text(&self) -> Vec<String> {
+        let mut rows: Vec<String> = (0..self.rows).map(|row| self.row_text(row)).collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        rows
+    }
+
+    fn scroll(&mut self) {
+        self.cells.remove(0);
+        self.cells.push(vec![Cell::Char(' '); self.cols]);
+    }
+
+    fn line_feed(&mut self) {
+        if self.row + 1 == self.rows { self.scroll(); } else { self.row += 1; }
+    }
+
+    fn erase(&mut self, row: usize, from: usize, to: usize) {
+        for col in from..to.min(self.cols) {
+            self.cells[row][col] = Cell::Char(' ');
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        let width = char_width(c);
+        if width == 0 { return; }
+        if self.wrap_pending || self.col + width > self.cols {
+            self.col = 0;
+            self.line_feed();
+        }
+        self.cells[self.row][self.col] = Cell::Char(c);
+        if width == 2 {
+            self.cells[self.row][self.col + 1] = Cell::Continuation;
+        }
+        // like xterm the cursor stays on the last column
+        self.wrap_pending = self.col + width == self.cols;
+        self.col = (self.col + width).min(self.cols - 1);
+    }
+
+    fn csi(&mut self, seq: &str) {
+        let (params, f) = seq.split_at(seq.len() - 1);
+        let f = f.chars().next().unwrap();
+        let mut pn = params.split(';').map(|p| p.parse::<usize>().ok());
+        let p0 = pn.next().flatten();
+        let p1 = pn.next().flatten();
+        let n = p0.unwrap_or(1).max(1);
+        if f != 'm' {
+            self.wrap_pending = false;
+        }
+        match f {
+            'A' => self.row = self.row.saturating_sub(n),
+            'B' => self.row = (self.row + n).min(self.rows - 1),
+            'C' => self.col = (self.col + n).min(self.cols - 1),
+            'D' => self.col = self.col.saturating_sub(n),
+            'G' => self.col = (n - 1).min(self.cols - 1),
+            'H' => {
+                self.row = (n - 1).min(self.rows - 1);
+                self.col = (p1.unwrap_or(1).max(1) - 1).min(self.cols - 1);
+            },
+            'K' => match p0.unwrap_or(0) {
+                0 => self.erase(self.row, self.col, self.cols),
+                1 => self.erase(self.row, 0, self.col + 1),
+                _ => self.erase(self.row, 0, self.cols),
+            },
+            'J' => match p0.unwrap_or(0) {
+                0 => {
+                    self.erase(self.row, self.col, self.cols);
+                    for row in self.row + 1..self.rows { self.erase(row, 0, self.cols); }
+                },
+                _ => {
+                    for row in 0..self.rows { self.erase(row, 0, self.cols); }
+                },
+            },
+            // colors are not modeled
+            'm' => (),
+            'n' if p0 == Some(6) => {
+                let reply = format!("\x1b[{};{}R", self.row + 1, self.col + 1);
+                self.replies.extend_from_slice(reply.as_bytes());
+            },
+            _ => panic!("unsupported sequence ESC [ {}", seq),
+        }
+    }
+
+    fn control(&mut self, c: char) {
+        if c != '\x07' {
+            self.wrap_pending = false;
+        }
+        match c {
+            '\r' => self.col = 0,
+            '\n' => self.line_feed(),
+            '\x07' => self.bells += 1,
+            '\x08' => self.col = self.col.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    // Takes what the screen has sent back.
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.replies)
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        let pending = core::mem::take(&mut self.pending);
+        let (text, rest) = match core::str::from_utf8(&pending) {
+            Ok(text) => (text, &[][..]),
+            Err(e) if e.error_len().is_none() => {
+                let (valid, rest) = pending.split_at(e.valid_up_to());
+                (core::str::from_utf8(valid).unwrap(), rest)
+            },
+            Err(e) => panic!("console sent invalid UTF-8: {:?}", e),
+        };
+        for c in text.chars() {
+            match self.sequence.as_mut() {
+                Some(seq) if seq.is_empty() => {
+                    match c {
+                        '[' => seq.push(c),
+                        '7' => { self.saved = (self.row, self.col, self.wrap_pending); self.sequence = None; },
+                        '8' => { (self.row, self.col, self.wrap_pending) = self.saved; self.sequence = None; },
+                        _ => panic!("unsupported sequence ESC {}", c),
+                    }
+                },
+                Some(seq) => {
+                    seq.push(c);
+                    if ('\x40'..='\x7e').contains(&c) {
+                        let seq = self.sequence.take().unwrap();
+                        self.csi(&seq[1..]);
+                    }
+                },
+                None if c == ESC => self.sequence = Some(String::new()),
+                None if c.is_control() => self.control(c),
+                None => self.put_char(c),
+            }
+        }
+        self.pending = rest.to_vec();
+    }
+}
+
+// Transport of the harness. Keystrokes are queued in `input`, everything
+// written goes to the screen and is also kept as raw bytes.
+pub struct VirtualTerminal {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    screen: Screen,
+}
+
+impl VirtualTerminal {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        VirtualTerminal {
+            input: VecDeque::new(),
+            output: Vec::new(),
+            screen: Screen::new(rows, cols),
+        }
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    // Drops the keys and replies not read yet, as if the terminal had
+    // not been attached.
+    pub fn discard_input(&mut self) {
+        self.input.clear();
+    }
+}
+
+impl Transport for VirtualTerminal {
+    type Error = Infallible;
+
+    fn read(&mut self) -> Result<Option<u8>, Infallible> {
+        Ok(self.input.pop_front())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Infallible> {
+        self.output.extend_from_slice(bytes);
+        self.screen.feed(bytes);
+        self.input.extend(self.screen.take_replies());
+        Ok(())
+    }
+}
+
+// InputHandler which keeps every line passed to input_str and counts
+// the overflows.
+#[derive(Clone, Default)]
+pub struct LineRecorder {
+    lines: Rc<RefCell<Vec<String>>>,
+    overflows: Rc<std::cell::Cell<usize>>,
+}
+
+impl LineRecorder {
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.borrow().clone()
+    }
+
+    pub fn overflows(&self) -> usize {
+        self.overflows.get()
+    }
+}
+
+impl InputHandler for LineRecorder {
+    fn input_str(&mut self, line: &str, _output: &mut Output) {
+        self.lines.borrow_mut().push(String::from(line));
+    }
+
+    fn overflow(&mut self) {
+        self.overflows.set(self.overflows.get() + 1);
+    }
+}
+
+pub struct Harness<H: InputHandler = LineRecorder> {
+    console: Console<VirtualTerminal, H>,
+}
+
+impl Harness<LineRecorder> {
+    // 24x80 terminal with a line buffer of `buffer_len` bytes.
+    pub fn new(prompt: &'static str, buffer_len: usize) -> (Self, LineRecorder) {
+        let recorder = LineRecorder::default();
+        (Harness::with_handler(prompt, buffer_len, 24, 80, recorder.clone()), recorder)
+    }
+}
+
+impl<H: InputHandler> Harness<H> {
+    pub fn with_handler(prompt: &'static str, buffer_len: usize, rows: usize, cols: usize, handler: H) -> Self {
+        let buffer: &'static mut [u8] = Vec::leak(vec![0u8; buffer_len]);
+        Harness {
+            console: Console::with_transport(buffer, prompt, VirtualTerminal::new(rows, cols), Some(handler)),
+        }
+    }
+
+    pub fn console(&mut self) -> &mut Console<VirtualTerminal, H> {
+        &mut self.console
+    }
+
+    pub fn terminal(&mut self) -> &mut VirtualTerminal {
+        self.console.transport()
+    }
+
+    // Feeds the keystrokes and lets the console process all of them.
+    pub fn keys(&mut self, keys: impl AsRef<[u8]>) -> &mut Self {
+        self.terminal().input.extend(keys.as_ref());
+        while !self.terminal().input.is_empty() {
+            let _ = self.console.input();
+        }
+        self
+    }
+
+    pub fn screen(&mut self) -> &Screen {
+        self.terminal().screen()
+    }
+
+    // The row the cursor is on.
+    pub fn line(&mut self) -> String {
+        let (row, _) = self.screen().cursor();
+        self.screen().row_text(row)
+    }
+
+    pub fn cursor_col(&mut self) -> usize {
+        self.screen().cursor().1
+    }
+}
@@ -0,0 +1,121 @@
+use core::convert::Infallible;
+
+#[cfg(feature = "embedded-hal-nb")]
+use embedded_hal_nb::nb;
+
+// Byte stream a Console runs on. `read` must not block, it returns
+// Ok(None) when nothing has been received.
+pub trait Transport {
+    type Error;
+
+    fn read(&mut self) -> Result<Option<u8>, Self::Error>;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+// The getc/putc closures Console::new has always taken.
+pub struct Closures<Getc, Putc>
+where
+    Getc: FnMut() -> Option<u8>,
+    Putc: FnMut(u8)
+{
+    getc: Getc,
+    putc: Putc,
+}
+
+impl<Getc, Putc> Closures<Getc, Putc>
+where
+    Getc: FnMut() -> Option<u8>,
+    Putc: FnMut(u8)
+{
+    pub fn new(getc: Getc, putc: Putc) -> Self {
+        Closures { getc, putc }
+    }
+}
+
+impl<Getc, Putc> Transport for Closures<Getc, Putc>
+where
+    Getc: FnMut() -> Option<u8>,
+    Putc: FnMut(u8)
+{
+    type Error = Infallible;
+
+    fn read(&mut self) -> Result<Option<u8>, Infallible> {
+        Ok((self.getc)())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Infallible> {
+        for &c in bytes {
+            (self.putc)(c);
+        }
+        Ok(())
+    }
+}
+
+// Any embedded-io port. ReadReady keeps `read` from blocking.
+#[cfg(feature = "embedded-io")]
+pub struct Io<T>(pub T);
+
+#[cfg(feature = "embedded-io")]
+impl<T> Transport for Io<T>
+where
+    T: embedded_io::Read + embedded_io::ReadReady + embedded_io::Write
+{
+    type Error = T::Error;
+
+    fn read(&mut self) -> Result<Option<u8>, T::Error> {
+        if !self.0.read_ready()? {
+            return Ok(None);
+        }
+        let mut c = [0u8; 1];
+        match self.0.read(&mut c)? {
+            0 => Ok(None),
+            _ => Ok(Some(c[0]))
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), T::Error> {
+        self.0.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> Result<(), T::Error> {
+        self.0.flush()
+    }
+}
+
+// Any embedded-hal-nb serial port, e.g. the Tx/Rx pair of a HAL USART.
+#[cfg(feature = "embedded-hal-nb")]
+pub struct Serial<Tx, Rx> {
+    pub tx: Tx,
+    pub rx: Rx,
+}
+
+#[cfg(feature = "embedded-hal-nb")]
+impl<Tx, Rx, E> Transport for Serial<Tx, Rx>
+where
+    Tx: embedded_hal_nb::serial::Write<u8, Error = E>,
+    Rx: embedded_hal_nb::serial::Read<u8, Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self) -> Result<Option<u8>, E> {
+        match self.rx.read() {
+            Ok(c) => Ok(Some(c)),
+            Err(nb::Error::WouldBlock) => Ok(None),
+            Err(nb::Error::Other(e)) => Err(e)
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        for &c in bytes {
+            nb::block!(self.tx.write(c))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), E> {
+        nb::block!(self.tx.flush())
+    }
+}
@@ -0,0 +1,113 @@
+// Helpers to edit UTF-8 text in a byte buffer and to know how many
+// terminal columns it takes.
+
+// East Asian Wide and Fullwidth ranges, enough for CJK text and emoji.
+const WIDE:[(u32, u32); 18] = [
+    (0x1100, 0x115f),
+    (0x231a, 0x231b),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xa960, 0xa97f),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe10, 0xfe19),
+    (0xfe30, 0xfe6f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x1f300, 0x1f64f),
+    (0x1f900, 0x1f9ff),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+];
+
+// Combining marks and other characters which do not move the cursor.
+const ZERO:[(u32, u32); 8] = [
+    (0x0300, 0x036f),
+    (0x0483, 0x0489),
+    (0x200b, 0x200f),
+    (0x20d0, 0x20ff),
+    (0x3099, 0x309a),
+    (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+    (0xe0100, 0xe01ef),
+];
+
+fn in_table(table: &[(u32, u32)], c: u32) -> bool {
+    table.binary_search_by(|&(first, last)| {
+        if last < c { core::cmp::Ordering::Less }
+        else if first > c { core::cmp::Ordering::Greater }
+        else { core::cmp::Ordering::Equal }
+    }).is_ok()
+}
+
+pub fn char_width(c: char) -> usize {
+    let c = c as u32;
+    if c < 0x20 || (0x7f..0xa0).contains(&c) { 0 }
+    else if c < 0x300 { 1 }
+    else if in_table(&ZERO, c) { 0 }
+    else if in_table(&WIDE, c) { 2 }
+    else { 1 }
+}
+
+// Columns taken by the bytes, an invalid sequence counts one column per byte.
+pub fn str_width(bytes: &[u8]) -> usize {
+    let mut width = 0;
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        match core::str::from_utf8(rest) {
+            Ok(s) => {
+                width += s.chars().map(char_width).sum::<usize>();
+                break;
+            },
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                width += unsafe { core::str::from_utf8_unchecked(valid) }.chars().map(char_width).sum::<usize>();
+                let skip = e.error_len().unwrap_or(invalid.len());
+                width += skip;
+                rest = &invalid[skip..];
+            }
+        }
+    }
+    width
+}
+
+pub fn is_continuation(c: u8) -> bool {
+    c & 0xc0 == 0x80
+}
+
+// Length of the sequence started by `c`, 0 if `c` can't start one.
+pub fn sequence_len(c: u8) -> usize {
+    match c {
+        0x00..=0x7f => 1,
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => 0
+    }
+}
+
+pub fn prev_boundary(bytes: &[u8], pos: usize) -> usize {
+    let mut p = pos;
+    while p > 0 {
+        p -= 1;
+        if !is_continuation(bytes[p]) { break; }
+    }
+    p
+}
+
+pub fn next_boundary(bytes: &[u8], pos: usize) -> usize {
+    let mut p = pos;
+    if p < bytes.len() { p += 1; }
+    while p < bytes.len() && is_continuation(bytes[p]) { p += 1; }
+    p
+}
+
+// Largest boundary which is not beyond `pos`.
+pub fn floor_boundary(bytes: &[u8], pos: usize) -> usize {
+    let mut p = pos.min(bytes.len());
+    while p > 0 && p < bytes.len() && is_continuation(bytes[p]) { p -= 1; }
+    p
+}
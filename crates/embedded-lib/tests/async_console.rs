@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+use embedded_io_async::{ErrorType, Read, Write};
+use embedded_lib::async_console::AsyncConsole;
+use embedded_lib::console::{InputHandler, Output};
+
+const SIZE: &str = "\x1b[24;80R";
+
+// The pipe never has to wait, one poll runs a future to the end.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("the pipe never waits"),
+    }
+}
+
+// Port with the keys to receive, one chunk per read, and what was written.
+#[derive(Default)]
+struct Pipe {
+    rx: VecDeque<Vec<u8>>,
+    tx: Vec<u8>,
+    flushes: usize,
+}
+
+impl ErrorType for Pipe {
+    type Error = Infallible;
+}
+
+impl Read for Pipe {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let chunk = self.rx.pop_front().unwrap_or_default();
+        buf[..chunk.len()].copy_from_slice(&chunk);
+        Ok(chunk.len())
+    }
+}
+
+impl Write for Pipe {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Infallible> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+// Answers every line with `len` bytes and keeps the results of its writes.
+// "wait N" keeps running for N polls.
+#[derive(Default)]
+struct Echo {
+    lines: Vec<String>,
+    results: Vec<std::fmt::Result>,
+    ticks: usize,
+}
+
+impl InputHandler for Echo {
+    fn input_str(&mut self, line: &str, output: &mut Output) {
+        self.lines.push(line.into());
+        let len: usize = line.strip_prefix("dump ").and_then(|n| n.parse().ok()).unwrap_or(0);
+        let result = match len {
+            0 => writeln!(output, "got {}", line),
+            len => writeln!(output, "{}", "x".repeat(len)),
+        };
+        self.results.push(result);
+        if let Some(ticks) = line.strip_prefix("wait ").and_then(|n| n.parse().ok()) {
+            self.ticks = ticks;
+            output.keep_running();
+        }
+    }
+
+    fn poll(&mut self, output: &mut Output) {
+        self.ticks -= 1;
+        let _ = writeln!(output, "tick");
+        if self.ticks > 0 {
+            output.keep_running();
+        }
+    }
+}
+
+fn console(tx_size: usize) -> AsyncConsole<Pipe, Echo> {
+    let buffer = Box::leak(vec![0u8; 64].into_boxed_slice());
+    let tx_buffer = Box::leak(vec![0u8; tx_size].into_boxed_slice());
+    AsyncConsole::new(buffer, tx_buffer, "> ", Pipe::default(), Some(Echo::default()))
+}
+
+fn receive(console: &mut AsyncConsole<Pipe, Echo>, keys: &str) -> String {
+    console.io().tx.clear();
+    console.io().rx.push_back(keys.into());
+    block_on(console.input()).unwrap();
+    String::from_utf8(console.io().tx.clone()).unwrap()
+}
+
+#[test]
+fn output_is_staged_until_written() {
+    let mut console = console(256);
+    // nothing is written before the console is driven
+    assert!(console.io().tx.is_empty());
+    block_on(console.redraw()).unwrap();
+    assert!(String::from_utf8(console.io().tx.clone()).unwrap().ends_with("> "));
+    assert_eq!(console.io().flushes, 1);
+
+    // the size asked at startup
+    assert_eq!(receive(&mut console, SIZE), "");
+    assert_eq!(receive(&mut console, "ab"), "ab");
+    assert_eq!(console.io().flushes, 2);
+}
+
+#[test]
+fn line_is_submitted_and_answered() {
+    let mut console = console(256);
+    receive(&mut console, SIZE);
+    let written = receive(&mut console, "led on\r");
+    assert_eq!(written, "led on\n\rgot led on\n\r> ");
+
+    // a line split over several reads
+    receive(&mut console, "le");
+    let written = receive(&mut console, "d off\r");
+    assert_eq!(written, "d off\n\rgot led off\n\r> ");
+}
+
+#[test]
+fn output_which_does_not_fit_fails_the_write() {
+    let mut console = console(128);
+    receive(&mut console, SIZE);
+    let written = receive(&mut console, "dump 50\r");
+    assert_eq!(written, format!("dump 50\n\r{}\n\r> ", "x".repeat(50)));
+
+    let written = receive(&mut console, "dump 500\r");
+    assert_eq!(written, format!("dump 500\n\r{}\n\r\r\x1b[J> ", "x".repeat(118)));
+
+    // the prompt is drawn again, the next chunk has the whole buffer
+    assert_eq!(receive(&mut console, "\r"), "\n\rgot \n\r> ");
+}
+
+#[test]
+fn handler_sees_the_failed_write() {
+    let mut console = console(128);
+    receive(&mut console, SIZE);
+    receive(&mut console, "dump 50\r");
+    receive(&mut console, "dump 500\r");
+    receive(&mut console, "\r");
+    // the second command's write failed, the next line works again
+    let echo = console.handler().unwrap();
+    assert_eq!(echo.lines, ["dump 50", "dump 500", ""]);
+    assert_eq!(echo.results, [Ok(()), Err(std::fmt::Error), Ok(())]);
+}
+
+#[test]
+fn running_command_is_polled_without_input() {
+    let mut console = console(256);
+    receive(&mut console, SIZE);
+    receive(&mut console, "wait 2\r");
+    assert!(console.is_running());
+    console.io().tx.clear();
+    block_on(console.poll()).unwrap();
+    block_on(console.poll()).unwrap();
+    assert!(!console.is_running());
+    assert_eq!(String::from_utf8(console.io().tx.clone()).unwrap(), "tick\n\rtick\n\r> ");
+}
+
+#[test]
+fn line_is_redrawn_after_dropped_output() {
+    let mut console = console(64);
+    receive(&mut console, SIZE);
+    let written = receive(&mut console, "dump 200\r");
+    assert!(written.ends_with("xxx\n\r\r\x1b[J> "));
+    let written = receive(&mut console, "ab");
+    assert_eq!(written, "ab");
+    assert_eq!(receive(&mut console, "\r"), "\n\rgot ab\n\r> ");
+}
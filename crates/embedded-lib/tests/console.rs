@@ -0,0 +1,639 @@
+use std::fmt::Write;
+
+use embedded_lib::command::{Args, Command, CommandError, Shell};
+use embedded_lib::console::{CancelToken, Color, InputNewline, Output, OutputNewline, SecretMode};
+use embedded_lib::history::History;
+use embedded_lib::testing::{Harness, LineRecorder};
+
+const LEFT: &str = "\x1b[D";
+const RIGHT: &str = "\x1b[C";
+const UP: &str = "\x1b[A";
+const DOWN: &str = "\x1b[B";
+const DELETE: &str = "\x1b[3~";
+const BS: &str = "\x7f";
+
+#[test]
+fn prompt_is_shown() {
+    let (mut term, lines) = Harness::new("cm7> ", 64);
+    assert_eq!(term.line(), "cm7>");
+    assert_eq!(term.cursor_col(), 5);
+    assert!(lines.lines().is_empty());
+}
+
+#[test]
+fn typed_line_is_submitted() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("led on");
+    assert_eq!(term.line(), "> led on");
+    assert_eq!(term.cursor_col(), 8);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["led on"]);
+    assert_eq!(term.line(), ">");
+    assert_eq!(term.screen().text(), ["> led on", ">"]);
+}
+
+#[test]
+fn insert_in_the_middle() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("acd").keys(LEFT).keys(LEFT).keys("b");
+    assert_eq!(term.line(), "> abcd");
+    assert_eq!(term.cursor_col(), 4);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["abcd"]);
+}
+
+#[test]
+fn insert_at_the_start() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("bc").keys(LEFT).keys(LEFT).keys("a");
+    assert_eq!(term.line(), "> abc");
+    assert_eq!(term.cursor_col(), 3);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["abc"]);
+}
+
+#[test]
+fn backspace_at_the_end() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("abc").keys(BS);
+    assert_eq!(term.line(), "> ab");
+    assert_eq!(term.cursor_col(), 4);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["ab"]);
+}
+
+#[test]
+fn backspace_in_the_middle() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("abxc").keys(LEFT).keys(BS);
+    assert_eq!(term.line(), "> abc");
+    assert_eq!(term.cursor_col(), 4);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["abc"]);
+}
+
+#[test]
+fn backspace_at_the_start_does_nothing() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys(BS);
+    assert_eq!(term.line(), ">");
+    assert_eq!(term.cursor_col(), 2);
+    term.keys("ab").keys(LEFT).keys(LEFT).keys(BS).keys(BS);
+    assert_eq!(term.line(), "> ab");
+    assert_eq!(term.cursor_col(), 2);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["ab"]);
+}
+
+#[test]
+fn delete_under_the_cursor() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("abxc").keys(LEFT).keys(LEFT).keys(DELETE);
+    assert_eq!(term.line(), "> abc");
+    assert_eq!(term.cursor_col(), 4);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["abc"]);
+}
+
+#[test]
+fn delete_at_the_end_does_nothing() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("abc").keys(DELETE).keys(DELETE);
+    assert_eq!(term.line(), "> abc");
+    assert_eq!(term.cursor_col(), 5);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["abc"]);
+}
+
+#[test]
+fn delete_everything_from_the_start() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("abc").keys(LEFT.repeat(3)).keys(DELETE.repeat(4));
+    assert_eq!(term.line(), ">");
+    assert_eq!(term.cursor_col(), 2);
+    term.keys("\r");
+    assert_eq!(lines.lines(), [""]);
+}
+
+#[test]
+fn cursor_stops_at_both_ends() {
+    let (mut term, _) = Harness::new("> ", 64);
+    term.keys("ab").keys(RIGHT);
+    assert_eq!(term.cursor_col(), 4);
+    term.keys(LEFT.repeat(5));
+    assert_eq!(term.cursor_col(), 2);
+    term.keys(RIGHT);
+    assert_eq!(term.cursor_col(), 3);
+}
+
+#[test]
+fn insert_up_to_the_last_byte() {
+    let (mut term, _) = Harness::new("> ", 4);
+    term.keys("acd").keys(LEFT).keys(LEFT).keys("b");
+    assert_eq!(term.line(), "> abcd");
+    assert_eq!(term.cursor_col(), 4);
+}
+
+#[test]
+fn line_editing_keys() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("world\x01hello \x05!");
+    assert_eq!(term.line(), "> hello world!");
+    term.keys("\x1b[1;5D\x0b");
+    assert_eq!(term.line(), "> hello");
+    term.keys("\x17bye");
+    assert_eq!(term.line(), "> bye");
+    term.keys("\x15ok\r");
+    assert_eq!(lines.lines(), ["ok"]);
+}
+
+#[test]
+fn unknown_sequences_do_not_reach_the_line() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("a\x1b[?25hb\x1b]0;title\x07c\x1b[99~d\r");
+    assert_eq!(lines.lines(), ["abcd"]);
+}
+
+#[test]
+fn enter_after_a_lone_escape_submits() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("abc\x1b\rdef\r");
+    assert_eq!(lines.lines(), ["abc", "def"]);
+}
+
+#[test]
+fn wide_characters() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("日本語").keys(LEFT);
+    assert_eq!(term.line(), "> 日本語");
+    assert_eq!(term.cursor_col(), 6);
+    term.keys(BS);
+    assert_eq!(term.line(), "> 日語");
+    assert_eq!(term.cursor_col(), 4);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["日語"]);
+}
+
+#[test]
+fn print_above_keeps_the_edit_line() {
+    let (mut term, lines) = Harness::new("cm7> ", 64);
+    term.keys("led of").keys(LEFT);
+    let _ = term.console().print_above(">cm4> hello");
+    assert_eq!(term.screen().text(), [">cm4> hello", "cm7> led of"]);
+    assert_eq!(term.cursor_col(), 10);
+    let _ = term.console().print_above("two\nlines\n");
+    assert_eq!(term.screen().text(), [">cm4> hello", "two", "lines", "cm7> led of"]);
+    term.keys("f").keys("\x05\r");
+    assert_eq!(lines.lines(), ["led off"]);
+}
+
+fn login(_: &mut Vec<String>, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    args.finish()?;
+    output.read_secret(SecretMode::Masked, "password: ");
+    Ok(())
+}
+
+fn check_password(secrets: &mut Vec<String>, secret: &str, _: &mut Output) -> Result<(), CommandError> {
+    secrets.push(String::from(secret));
+    if secret == "s3cret" { Ok(()) } else { Err(CommandError::Failed("wrong password")) }
+}
+
+const LOGIN: [Command<Vec<String>>; 1] = [
+    Command::new("login", "login", login).with_secret(check_password),
+];
+
+#[test]
+fn secret_is_masked_and_passed_to_the_command() {
+    let mut term = Harness::with_handler("> ", 64, 24, 80, Shell::new(&LOGIN, Vec::new()));
+    term.keys("login\r");
+    assert_eq!(term.line(), "password:");
+    term.keys("s3x").keys(BS).keys("cret");
+    assert_eq!(term.line(), "password: ******");
+    assert_eq!(term.cursor_col(), 16);
+    term.keys("\r");
+    assert_eq!(term.line(), ">");
+    term.keys("login\rwrong\r");
+    assert_eq!(term.console().handler().unwrap().context(), &["s3cret", "wrong"]);
+    assert_eq!(term.screen().text().last().unwrap(), ">");
+    assert!(term.screen().text().contains(&String::from("login: wrong password")));
+    assert!(!String::from_utf8_lossy(term.terminal().output()).contains("s3cret"));
+}
+
+#[test]
+fn secret_is_not_kept_in_history() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.console().set_history(History::new(Vec::leak(vec![0u8; 64 * 5]), 4));
+    term.keys("first\r");
+    term.console().read_secret(SecretMode::Hidden, "pin: ");
+    term.keys("1234").keys("\x1b[A");
+    assert_eq!(term.line(), "pin:");
+    assert_eq!(term.cursor_col(), 5);
+    term.keys("\r\x1b[A");
+    assert_eq!(term.line(), "> first");
+    assert_eq!(lines.lines(), ["first"]);
+}
+
+#[test]
+fn any_newline_submits_once() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("cr\rlf\ncrlf\r\nlfcr\n\rcrnul\r\0\r\n\n");
+    assert_eq!(lines.lines(), ["cr", "lf", "crlf", "lfcr", "crnul", "", ""]);
+}
+
+#[test]
+fn newline_policies() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.console().set_newline(InputNewline::Lf, OutputNewline::CrLf);
+    term.terminal().clear_output();
+    term.keys("a\rb\n");
+    assert_eq!(lines.lines(), ["ab"]);
+    assert_eq!(term.terminal().output(), b"ab\r\n> ");
+    term.console().set_newline(InputNewline::Cr, OutputNewline::LfCr);
+    term.keys("c\nd\r");
+    assert_eq!(lines.lines(), ["ab", "cd"]);
+}
+
+#[test]
+fn full_line_rings_the_bell() {
+    let (mut term, lines) = Harness::new("> ", 4);
+    term.keys("abcde");
+    assert_eq!(term.line(), "> abcd");
+    assert_eq!(term.screen().bells(), 1);
+    assert_eq!(lines.overflows(), 1);
+    term.keys(LEFT).keys(BS).keys("日").keys("x\r");
+    assert_eq!(term.screen().bells(), 2);
+    assert_eq!(lines.lines(), ["abxd"]);
+}
+
+#[test]
+fn set_prompt_shows_on_redraw() {
+    let (mut term, lines) = Harness::new("cm7> ", 64);
+    term.keys("led");
+    term.console().set_prompt("cm7(2)> ");
+    assert_eq!(term.line(), "cm7> led");
+    let _ = term.console().redraw();
+    assert_eq!(term.line(), "cm7(2)> led");
+    assert_eq!(term.cursor_col(), 11);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["led"]);
+    assert_eq!(term.line(), "cm7(2)>");
+}
+
+fn cd(dir: &mut &'static str, args: &mut Args, _: &mut Output) -> Result<(), CommandError> {
+    let name = args.required::<&str>()?;
+    args.finish()?;
+    *dir = match name {
+        "/" => "/",
+        "gpio" => "/gpio",
+        _ => return Err(CommandError::InvalidArgument(1)),
+    };
+    Ok(())
+}
+
+fn dir_prompt(dir: &&'static str, last_error: Option<CommandError>, output: &mut Output) {
+    if last_error.is_some() {
+        output.set_color(Some(Color::Red));
+        output.write_bytes(b"! ");
+        output.set_color(None);
+    }
+    output.write_bytes(dir.as_bytes());
+    output.write_bytes(b"> ");
+}
+
+const CD: [Command<&'static str>; 1] = [
+    Command::new("cd", "cd <dir>", cd),
+];
+
+#[test]
+fn shell_prompt_shows_its_state() {
+    let shell = Shell::new(&CD, "/").with_prompt(dir_prompt);
+    let mut term = Harness::with_handler("> ", 64, 24, 80, shell);
+    assert_eq!(term.line(), "/>");
+    term.keys("cd gpio\r");
+    assert_eq!(term.line(), "/gpio>");
+    term.terminal().clear_output();
+    term.keys("cd x\r");
+    assert_eq!(term.line(), "! /gpio>");
+    assert!(term.terminal().output().ends_with(b"\x1b[31m! \x1b[0m/gpio> "));
+    term.keys("cd /\r");
+    assert_eq!(term.line(), "/>");
+}
+
+#[test]
+fn ctrl_c_discards_the_line() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("led on").keys(LEFT.repeat(3)).keys("\x03");
+    assert_eq!(term.screen().text(), ["> led on^C", ">"]);
+    term.keys("x\r");
+    assert_eq!(lines.lines(), ["x"]);
+}
+
+#[test]
+fn ctrl_d_and_ctrl_l() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("one\rabc").keys(LEFT).keys("\x04");
+    assert_eq!(term.line(), "> ab");
+    term.keys("\x0c");
+    assert_eq!(term.screen().text(), ["> ab"]);
+    assert_eq!(term.cursor_col(), 4);
+    term.keys("\r");
+    assert_eq!(lines.lines(), ["one", "ab"]);
+}
+
+static CANCEL: CancelToken = CancelToken::new();
+
+fn count(n: &mut u32, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    *n = args.required()?;
+    args.finish()?;
+    output.keep_running();
+    Ok(())
+}
+
+fn count_poll(n: &mut u32, output: &mut Output) -> Result<(), CommandError> {
+    if output.cancelled() {
+        return Err(CommandError::Failed("stopped"));
+    }
+    let _ = writeln!(output, "{}", n);
+    *n -= 1;
+    if *n > 0 {
+        output.keep_running();
+    }
+    Ok(())
+}
+
+const COUNT: [Command<u32>; 1] = [
+    Command::new("count", "count <n>", count).with_poll(count_poll),
+];
+
+#[test]
+fn command_keeps_running_until_done_or_cancelled() {
+    let mut term = Harness::with_handler("> ", 64, 24, 80, Shell::new(&COUNT, 0));
+    term.console().set_cancel_token(&CANCEL);
+    term.keys("count 3\r");
+    assert!(term.console().is_running());
+    assert_eq!(term.screen().text(), ["> count 3"]);
+    for _ in 0..5 {
+        let _ = term.console().input();
+    }
+    assert!(!term.console().is_running());
+    assert_eq!(term.screen().text(), ["> count 3", "3", "2", "1", ">"]);
+
+    term.keys("count 100\r");
+    let _ = term.console().input();
+    term.keys("\x03");
+    assert!(!term.console().is_running());
+    assert!(CANCEL.is_cancelled());
+    assert_eq!(term.screen().text()[4..], ["> count 100", "100", "count: stopped", "^C", ">"]);
+    term.keys("count 1\r");
+    assert!(!CANCEL.is_cancelled());
+}
+
+#[test]
+fn newline_after_a_command_is_not_taken_for_its_pair() {
+    let mut term = Harness::with_handler("> ", 64, 24, 80, Shell::new(&COUNT, 0));
+    // the LF of the CR LF goes to the running command
+    term.keys("count 2\r").keys("\n");
+    let _ = term.console().input();
+    assert!(!term.console().is_running());
+    assert_eq!(term.screen().text(), ["> count 2", "2", "1", ">"]);
+    term.keys("\n");
+    assert_eq!(term.screen().text(), ["> count 2", "2", "1", ">", ">"]);
+}
+
+#[test]
+fn terminal_size_is_queried_at_startup() {
+    let mut term = Harness::with_handler("> ", 64, 5, 10, LineRecorder::default());
+    assert_eq!(term.console().size(), None);
+    term.keys("");
+    assert_eq!(term.console().size(), Some((5, 10)));
+    assert_eq!(term.screen().cursor(), (0, 2));
+}
+
+#[test]
+fn size_is_asked_again_on_the_first_key() {
+    let mut term = Harness::with_handler("> ", 64, 5, 30, LineRecorder::default());
+    term.terminal().discard_input();
+    term.keys("");
+    assert_eq!(term.console().size(), None);
+    term.keys("a");
+    assert_eq!(term.console().size(), Some((5, 30)));
+    term.keys("bcdefghijklmnopqrstuvwxyz0123456789");
+    assert_eq!(term.screen().text(), ["> abcdefghijklmnopqrstuvwxyz01", "23456789"]);
+    assert_eq!(term.screen().cursor(), (1, 8));
+    term.keys(LEFT.repeat(10));
+    assert_eq!(term.screen().cursor(), (0, 28));
+}
+
+#[test]
+fn redraw_asks_for_the_size_again() {
+    let mut term = Harness::with_handler("> ", 64, 5, 30, LineRecorder::default());
+    term.terminal().discard_input();
+    let _ = term.console().redraw();
+    term.keys("");
+    assert_eq!(term.console().size(), Some((5, 30)));
+}
+
+#[test]
+fn function_keys_are_not_taken_as_the_size() {
+    let mut term = Harness::with_handler("> ", 64, 5, 30, LineRecorder::default());
+    term.terminal().discard_input();
+    // Shift-F3 and Ctrl-F3
+    term.keys("\x1b[1;2R\x1b[1;5R");
+    assert_eq!(term.console().size(), None);
+    assert_eq!(term.line(), ">");
+    term.keys("x");
+    assert_eq!(term.console().size(), Some((5, 30)));
+}
+
+#[test]
+fn long_lines_wrap() {
+    let recorder = LineRecorder::default();
+    let mut term = Harness::with_handler("> ", 64, 5, 10, recorder.clone());
+    term.keys("abcdefgh");
+    assert_eq!(term.screen().text(), ["> abcdefgh"]);
+    assert_eq!(term.screen().cursor(), (1, 0));
+    term.keys("ijkX");
+    assert_eq!(term.screen().text(), ["> Xabcdefg", "hijk"]);
+    assert_eq!(term.screen().cursor(), (0, 3));
+    term.keys("").keys(LEFT).keys(LEFT).keys(LEFT).keys(LEFT).keys(LEFT);
+    assert_eq!(term.screen().cursor(), (0, 9));
+    term.keys("");
+    assert_eq!(term.screen().text(), ["> Xabcdef"]);
+    term.keys("\r");
+    assert_eq!(recorder.lines(), ["Xabcdef"]);
+    assert_eq!(term.screen().text(), ["> Xabcdef", ">"]);
+}
+
+#[test]
+fn wrapped_line_is_redrawn_in_place() {
+    let recorder = LineRecorder::default();
+    let mut term = Harness::with_handler("> ", 64, 5, 10, recorder.clone());
+    term.console().set_history(History::new(Vec::leak(vec![0u8; 128]), 4));
+    term.keys("abcdefghijklmnopq\rx").keys(UP);
+    assert_eq!(term.screen().text(), ["> abcdefgh", "ijklmnopq", "> abcdefgh", "ijklmnopq"]);
+    term.keys("").keys(RIGHT);
+    let _ = term.console().print_above("hello");
+    assert_eq!(term.screen().text(), ["> abcdefgh", "ijklmnopq", "hello", "> abcdefgh", "ijklmnopq"]);
+    assert_eq!(term.screen().cursor(), (3, 3));
+    term.keys(DOWN);
+    assert_eq!(term.screen().text(), ["> abcdefgh", "ijklmnopq", "hello", "> x"]);
+}
+
+// Terminal which checks that nothing is written between XOFF and XON.
+struct SlowTerminal {
+    input: std::collections::VecDeque<u8>,
+    stopped: bool,
+    output: Vec<u8>,
+}
+
+impl embedded_lib::transport::Transport for SlowTerminal {
+    type Error = std::convert::Infallible;
+
+    fn read(&mut self) -> Result<Option<u8>, Self::Error> {
+        let c = self.input.pop_front();
+        match c {
+            Some(0x13) => self.stopped = true,
+            Some(0x11) => self.stopped = false,
+            _ => ()
+        }
+        Ok(c)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        assert!(!self.stopped, "written after XOFF");
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[test]
+fn xoff_holds_back_the_output() {
+    let terminal = SlowTerminal { input: b"ab\x13cd\x11e\r".iter().copied().collect(), stopped: false, output: Vec::new() };
+    let recorder = embedded_lib::testing::LineRecorder::default();
+    let buffer = Vec::leak(vec![0u8; 64]);
+    let mut console = embedded_lib::console::Console::with_transport(buffer, "> ", terminal, Some(recorder.clone()));
+    console.set_flow_control(true);
+    // keys read while output is written are kept for later calls
+    for _ in 0..16 {
+        let _ = console.input();
+    }
+    assert_eq!(recorder.lines(), ["abcde"]);
+    // the terminal never answers, the size is asked again on the first key
+    assert_eq!(console.transport().output, b"\x1b7\x1b[999;999H\x1b[6n\x1b8> \x1b7\x1b[999;999H\x1b[6n\x1b8abcde\n\r> ");
+}
+
+#[test]
+fn cursor_moves_relative_without_the_size() {
+    let keys = format!("{}{}", "x".repeat(100), "\x1b[H");
+    let terminal = SlowTerminal { input: keys.bytes().collect(), stopped: false, output: Vec::new() };
+    let buffer = Vec::leak(vec![0u8; 128]);
+    let mut console = embedded_lib::console::Console::with_transport(buffer, "> ", terminal, Some(LineRecorder::default()));
+    while !console.transport().input.is_empty() {
+        let _ = console.input();
+    }
+    assert_eq!(console.size(), None);
+    assert!(console.transport().output.ends_with(format!("{}\x1b[100D", "x".repeat(100)).as_bytes()));
+}
+
+// Terminal which sends XOFF once `xoff_after` bytes have come, and XON
+// a few reads later.
+struct FillingTerminal {
+    input: std::collections::VecDeque<u8>,
+    xoff_after: usize,
+    written: usize,
+    stopped: bool,
+    reads_stopped: usize,
+    output: Vec<u8>,
+}
+
+impl embedded_lib::transport::Transport for FillingTerminal {
+    type Error = std::convert::Infallible;
+
+    fn read(&mut self) -> Result<Option<u8>, Self::Error> {
+        if self.stopped {
+            self.reads_stopped += 1;
+            if self.reads_stopped == 3 {
+                self.stopped = false;
+                return Ok(Some(0x11));
+            }
+            return Ok(None);
+        }
+        let c = self.input.pop_front();
+        if c == Some(0x13) {
+            self.stopped = true;
+        }
+        Ok(c)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        assert!(!self.stopped, "written after XOFF");
+        self.output.extend_from_slice(bytes);
+        self.written += bytes.len();
+        if self.written >= self.xoff_after && self.xoff_after > 0 {
+            self.xoff_after = 0;
+            self.input.push_back(0x13);
+        }
+        Ok(())
+    }
+}
+
+struct Dump;
+
+impl embedded_lib::console::InputHandler for Dump {
+    fn input_str(&mut self, _: &str, output: &mut Output) {
+        for i in 0..50 {
+            let _ = writeln!(output, "{:08x}: 0000 0000 0000 0000", i * 16);
+        }
+    }
+}
+
+#[test]
+fn xoff_holds_back_the_output_of_a_command() {
+    let terminal = FillingTerminal { input: b"d\r".iter().copied().collect(), xoff_after: 300, written: 0, stopped: false, reads_stopped: 0, output: Vec::new() };
+    let buffer = Vec::leak(vec![0u8; 64]);
+    let mut console = embedded_lib::console::Console::with_transport(buffer, "> ", terminal, Some(Dump));
+    console.set_flow_control(true);
+    for _ in 0..4 {
+        let _ = console.input();
+    }
+    let terminal = console.transport();
+    assert_eq!(terminal.reads_stopped, 3);
+    let text = String::from_utf8(terminal.output.clone()).unwrap();
+    assert!(text.contains("00000310: 0000 0000 0000 0000\n\r> "));
+}
+
+#[test]
+fn xon_xoff_are_ignored_without_flow_control() {
+    let (mut term, lines) = Harness::new("> ", 64);
+    term.keys("a\x13b\x11c\r");
+    assert_eq!(lines.lines(), ["abc"]);
+}
+
+fn list(_: &mut u32, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    args.finish()?;
+    for n in 0..30 {
+        let _ = writeln!(output, "line {}", n);
+    }
+    Ok(())
+}
+
+const LIST: [Command<u32>; 1] = [
+    Command::new("list", "list", list),
+];
+
+#[test]
+fn pager_waits_after_each_screen() {
+    let mut term = Harness::with_handler("> ", 64, 10, 80, Shell::new(&LIST, 0));
+    term.console().set_pager(Some(10));
+    term.keys("list\r \rq");
+    let output = String::from_utf8(term.terminal().output().to_vec()).unwrap();
+    assert_eq!(output.matches("--More--").count(), 3);
+    assert!(output.contains("line 18\n"));
+    assert!(!output.contains("line 19\n"));
+    assert_eq!(term.screen().text().last().unwrap(), ">");
+    assert_eq!(term.screen().row_text(8), "line 18");
+    // the keys have to be there before the pager waits for them
+    term.keys("list\r   ");
+    let output = String::from_utf8(term.terminal().output().to_vec()).unwrap();
+    assert!(output.ends_with("line 29\n\r> "));
+}
@@ -0,0 +1,167 @@
+// Random keystroke streams against a model of the line editor.
+
+use embedded_lib::history::History;
+use embedded_lib::testing::{Harness, LineRecorder};
+use proptest::prelude::*;
+
+#[derive(Debug, Clone)]
+enum Key {
+    Char(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Enter,
+}
+
+impl Key {
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Key::Char(c) => c.to_string().into_bytes(),
+            Key::Backspace => b"\x7f".to_vec(),
+            Key::Delete => b"\x1b[3~".to_vec(),
+            Key::Left => b"\x1b[D".to_vec(),
+            Key::Right => b"\x1b[C".to_vec(),
+            Key::Home => b"\x01".to_vec(),
+            Key::End => b"\x05".to_vec(),
+            Key::Enter => b"\r".to_vec(),
+        }
+    }
+}
+
+fn key() -> impl Strategy<Value = Key> {
+    prop_oneof![
+        6 => prop::sample::select(vec!['a', 'z', ' ', '~', 'é', '日', '😀']).prop_map(Key::Char),
+        1 => Just(Key::Backspace),
+        1 => Just(Key::Delete),
+        1 => Just(Key::Left),
+        1 => Just(Key::Right),
+        1 => Just(Key::Home),
+        1 => Just(Key::End),
+        1 => Just(Key::Enter),
+    ]
+}
+
+// What the console should do with the keys.
+#[derive(Default)]
+struct Model {
+    line: Vec<char>,
+    cursor: usize,
+    lines: Vec<String>,
+    bells: usize,
+}
+
+impl Model {
+    fn press(&mut self, key: &Key, capacity: usize) {
+        match *key {
+            Key::Char(c) => {
+                let len: usize = self.line.iter().map(|c| c.len_utf8()).sum();
+                if len + c.len_utf8() > capacity {
+                    self.bells += 1;
+                }
+                else {
+                    self.line.insert(self.cursor, c);
+                    self.cursor += 1;
+                }
+            },
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            },
+            Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            },
+            Key::Left if self.cursor > 0 => self.cursor -= 1,
+            Key::Right if self.cursor < self.line.len() => self.cursor += 1,
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.line.len(),
+            Key::Enter => {
+                self.lines.push(self.line.drain(..).collect());
+                self.cursor = 0;
+            },
+            _ => (),
+        }
+    }
+
+    fn columns(chars: &[char]) -> usize {
+        chars.iter().map(|&c| embedded_lib::unicode::char_width(c)).sum()
+    }
+
+    // Rows of "> " and the line on a terminal `cols` wide, and the row and
+    // column of the cursor. A wide character which doesn't fit on the last
+    // column goes to the next row.
+    fn wrapped(&self, cols: usize) -> (Vec<String>, (usize, usize)) {
+        let mut rows = vec![String::from("> ")];
+        let (mut row, mut col) = (0, 2);
+        let mut cursor = (row, col);
+        for (i, &c) in self.line.iter().enumerate() {
+            let width = embedded_lib::unicode::char_width(c);
+            if col + width > cols {
+                rows.push(String::new());
+                (row, col) = (row + 1, 0);
+            }
+            rows[row].push(c);
+            col += width;
+            if col == cols {
+                rows.push(String::new());
+                (row, col) = (row + 1, 0);
+            }
+            if i + 1 == self.cursor {
+                cursor = (row, col);
+            }
+        }
+        (rows, cursor)
+    }
+}
+
+proptest! {
+    #[test]
+    fn editing_matches_the_model(capacity in 0usize..24, keys in prop::collection::vec(key(), 0..200)) {
+        let (mut term, recorder) = Harness::new("> ", capacity);
+        let mut model = Model::default();
+        for key in &keys {
+            term.keys(key.bytes());
+            model.press(key, capacity);
+        }
+        prop_assert_eq!(recorder.lines(), model.lines);
+        prop_assert_eq!(recorder.overflows(), model.bells);
+        prop_assert_eq!(term.screen().bells(), model.bells);
+        let line = format!("> {}", model.line.iter().collect::<String>());
+        prop_assert_eq!(term.line(), line.trim_end());
+        prop_assert_eq!(term.cursor_col(), 2 + Model::columns(&model.line[..model.cursor]));
+    }
+
+    #[test]
+    fn wrapped_editing_matches_the_model(capacity in 0usize..40, keys in prop::collection::vec(key(), 0..200)) {
+        let recorder = LineRecorder::default();
+        let mut term = Harness::with_handler("> ", capacity, 24, 10, recorder.clone());
+        let mut model = Model::default();
+        for key in &keys {
+            term.keys(key.bytes());
+            model.press(key, capacity);
+        }
+        prop_assert_eq!(&recorder.lines(), &model.lines);
+        let (rows, (row, col)) = model.wrapped(10);
+        let (screen_row, screen_col) = term.screen().cursor();
+        prop_assert_eq!(screen_col, col);
+        prop_assert!(screen_row >= row);
+        let top = screen_row - row;
+        for (i, text) in rows.iter().enumerate() {
+            if top + i < 24 {
+                prop_assert_eq!(term.screen().row_text(top + i), text.trim_end());
+            }
+        }
+    }
+
+    #[test]
+    fn any_input_stays_in_bounds(capacity in 0usize..16, input in prop::collection::vec(any::<u8>(), 0..400)) {
+        let (mut term, recorder) = Harness::new("> ", capacity);
+        term.console().set_history(History::new(Vec::leak(vec![0u8; 16 * 4]), 3));
+        term.keys(&input);
+        for line in recorder.lines() {
+            prop_assert!(line.len() <= capacity);
+        }
+    }
+}
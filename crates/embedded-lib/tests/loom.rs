@@ -0,0 +1,35 @@
+// Model checks the SPSC ring buffer, run with
+//
+//   RUSTFLAGS="--cfg loom" cargo test --release --test loom
+#![cfg(loom)]
+
+use embedded_lib::shared_ringbuffer::SharedRingBufferError;
+use embedded_lib::spsc_ringbuffer::SpscSharedRingBuffer;
+use loom::thread;
+
+const MESSAGES: [&[u8]; 3] = [b"ab", b"cdef", b"g"];
+
+#[test]
+fn messages_arrive_whole_and_in_order() {
+    loom::model(|| {
+        let (mut writer, mut reader) = SpscSharedRingBuffer::<4, 3>::pair();
+        let writer = thread::spawn(move || {
+            for message in MESSAGES {
+                while let Err(SharedRingBufferError::NoSpace) = writer.write(message) {
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut message = [0u8; 4];
+        for expected in MESSAGES {
+            let len = loop {
+                match reader.read(&mut message) {
+                    Ok(len) => break len,
+                    Err(_) => thread::yield_now(),
+                }
+            };
+            assert_eq!(&message[..len], expected);
+        }
+        writer.join().unwrap();
+    });
+}
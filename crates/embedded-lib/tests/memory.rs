@@ -0,0 +1,152 @@
+use embedded_lib::command::{Command, Shell};
+use embedded_lib::memory::{self, Access, MemoryMap, Peripheral, Region, Register};
+use embedded_lib::stm32h7;
+use embedded_lib::testing::Harness;
+
+// Host memory standing in for a RAM region, a flash region and the
+// register block of a peripheral.
+struct Target {
+    regions: &'static [Region],
+    peripherals: &'static [Peripheral],
+}
+
+impl MemoryMap for Target {
+    fn regions(&self) -> &[Region] {
+        self.regions
+    }
+
+    fn peripherals(&self) -> &[Peripheral] {
+        self.peripherals
+    }
+}
+
+static REGISTERS: [Register; 4] = [
+    Register::new("CR", 0x0, Access::ReadWrite),
+    Register::new("SR", 0x4, Access::ReadOnly),
+    Register::new("ICR", 0x8, Access::WriteOnly),
+    Register::new("FIFO", 0xc, Access::ReadSideEffect),
+];
+
+struct Memory {
+    ram: &'static mut [u32],
+    flash: usize,
+    block: usize,
+}
+
+fn harness() -> (Harness<Shell<'static, Target>>, Memory) {
+    let ram = Vec::leak((0..16u32).map(|i| 0x0403_0201 * (i + 1)).collect::<Vec<_>>());
+    let flash = Vec::leak(b"hello, world!\0\0\0".to_vec());
+    let block = Vec::leak(vec![0x11u32, 0x22, 0x33, 0x44]);
+    let memory = Memory { flash: flash.as_ptr() as usize, block: block.as_ptr() as usize, ram };
+    let target = Target {
+        regions: Vec::leak(vec![
+            Region { name: "RAM", start: memory.ram.as_ptr() as usize, size: 64, writable: true },
+            Region { name: "FLASH", start: memory.flash, size: 16, writable: false },
+        ]),
+        peripherals: Vec::leak(vec![
+            Peripheral { name: "UART", base: memory.block, size: 16, registers: &REGISTERS },
+        ]),
+    };
+    let commands: &'static [Command<Target>] = Vec::leak(vec![memory::md(), memory::mw(), memory::regdump()]);
+    (Harness::with_handler("> ", 64, 24, 80, Shell::new(commands, target)), memory)
+}
+
+#[test]
+fn md_shows_words_halves_and_bytes() {
+    let (mut term, memory) = harness();
+    let ram = memory.ram.as_ptr() as usize;
+    term.keys(format!("md {:#x} 5\r", ram));
+    assert_eq!(term.screen().text()[1..], [
+        format!("{:08x}: 04030201 08060402 0c090603 100c0804", ram),
+        format!("{:08x}: 140f0a05", ram + 16),
+        String::from(">"),
+    ]);
+    term.keys("\x0c").keys(format!("md {:#x} h 3\r", ram + 4));
+    assert_eq!(term.screen().text()[1], format!("{:08x}: 0402 0806 0603", ram + 4));
+    term.keys("\x0c").keys(format!("md {:#x} 14 b\r", memory.flash));
+    assert_eq!(term.screen().text()[1], format!("{:08x}: 68 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 00        hello, world!.", memory.flash));
+}
+
+#[test]
+fn md_refuses_unmapped_memory() {
+    let (mut term, memory) = harness();
+    let ram = memory.ram.as_ptr() as usize;
+    term.keys(format!("md {:#x} 17\r", ram));
+    assert_eq!(term.screen().text()[1], "md: address not mapped");
+    term.keys("\x0c").keys(format!("md {:#x}\r", ram + 2));
+    assert_eq!(term.screen().text()[1], "md: unaligned address");
+    term.keys("\x0c").keys(format!("md {:#x} 1 q\r", ram));
+    assert_eq!(term.screen().text()[1], "md: invalid argument #3");
+    term.keys("\x0c").keys(format!("md {:#x} 4\r", memory.block + 4));
+    assert_eq!(term.screen().text()[1], "md: address not mapped");
+}
+
+#[test]
+fn mw_writes_where_allowed() {
+    let (mut term, memory) = harness();
+    let ram = memory.ram.as_ptr() as usize;
+    term.keys(format!("mw {:#x} 0xdeadbeef\r", ram));
+    term.keys(format!("mw {:#x} 0xff b\r", ram + 5));
+    assert_eq!(memory.ram[0], 0xdeadbeef);
+    assert_eq!(memory.ram[1], 0x0806_ff02);
+    term.keys("\x0c").keys(format!("mw {:#x} 0x100 b\r", ram));
+    assert_eq!(term.screen().text()[1], "mw: invalid argument #2");
+    term.keys("\x0c").keys(format!("mw {:#x} 0\r", memory.flash));
+    assert_eq!(term.screen().text()[1], "mw: region is read-only");
+    term.keys(format!("mw {:#x} 0x55\r", memory.block));
+    assert_eq!(unsafe { *(memory.block as *const u32) }, 0x55);
+}
+
+#[test]
+fn regdump_reads_what_is_safe_to_read() {
+    let (mut term, memory) = harness();
+    term.keys("regdump\r");
+    assert_eq!(term.screen().text()[1], format!("UART      {:08x}", memory.block));
+    term.keys("\x0c").keys("regdump uart\r");
+    assert_eq!(term.screen().text()[1..6], [
+        format!("UART @ {:08x}", memory.block),
+        format!("  CR    {:08x}  00000011", memory.block),
+        format!("  SR    {:08x}  00000022", memory.block + 4),
+        format!("  ICR   {:08x}  (write-only)", memory.block + 8),
+        format!("  FIFO  {:08x}  (not read)", memory.block + 12),
+    ]);
+    term.keys("\x0c").keys("regdump UART sr\r");
+    assert_eq!(term.screen().text()[1], format!("  SR  {:08x}  00000022", memory.block + 4));
+    term.keys("\x0c").keys("regdump UART FIFO\r");
+    assert_eq!(term.screen().text()[1], "regdump: reading it has side effects, use md");
+    term.keys("\x0c").keys("regdump SPI\r");
+    assert_eq!(term.screen().text()[1], "regdump: invalid argument #1");
+}
+
+#[test]
+fn regdump_completes_peripherals_and_registers() {
+    let (mut term, _) = harness();
+    term.keys("regdump U\t");
+    assert_eq!(term.line(), "> regdump UART");
+    term.keys("F\t");
+    assert_eq!(term.line(), "> regdump UART FIFO");
+}
+
+#[test]
+fn stm32h7_table() {
+    let hsem = stm32h7::peripheral("HSEM").unwrap();
+    assert_eq!(hsem.base + hsem.register("C2IER").unwrap().offset, 0x5802_6510);
+    assert_eq!(hsem.register("RLR3").unwrap().access, Access::ReadSideEffect);
+    let tim3 = stm32h7::peripheral("tim3").unwrap();
+    assert_eq!(tim3.base + tim3.register("PSC").unwrap().offset, 0x4000_0428);
+    assert_eq!(tim3.register("DMAR").unwrap().access, Access::ReadSideEffect);
+    for peripheral in &stm32h7::PERIPHERALS {
+        for (i, register) in peripheral.registers.iter().enumerate() {
+            assert!(register.offset < peripheral.size);
+            assert!(peripheral.registers[..i].iter().all(|r| r.name != register.name && r.offset < register.offset));
+        }
+    }
+    let alias = stm32h7::CM4_REGIONS.iter().find(|r| r.start <= 0x1004_0000 && 0x1004_0000 < r.start + r.size).unwrap();
+    assert!(alias.writable);
+    assert!(!stm32h7::CM7_REGIONS.iter().any(|r| r.start <= 0x1004_0000 && 0x1004_0000 < r.start + r.size));
+    for regions in [&stm32h7::CM7_REGIONS[..], &stm32h7::CM4_REGIONS[..]] {
+        for (i, region) in regions.iter().enumerate() {
+            assert!(regions[..i].iter().all(|r| r.start + r.size <= region.start));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use embedded_lib::command::{Args, Command, CommandError, Shell};
+use embedded_lib::console::Output;
+use embedded_lib::script::ScriptShell;
+use embedded_lib::testing::Harness;
+
+fn led(log: &mut Vec<&'static str>, args: &mut Args, _: &mut Output) -> Result<(), CommandError> {
+    let on = args.required::<bool>()?;
+    args.finish()?;
+    log.push(if on { "on" } else { "off" });
+    Ok(())
+}
+
+const COMMANDS: [Command<Vec<&'static str>>; 1] = [
+    Command::new("led", "led on|off", led),
+];
+
+type Script = ScriptShell<'static, Vec<&'static str>, 2, 48>;
+
+fn harness(clock: fn() -> u32) -> Harness<Script> {
+    Harness::with_handler("> ", 64, 24, 80, ScriptShell::new(Shell::new(&COMMANDS, Vec::new()), clock))
+}
+
+fn log(term: &mut Harness<Script>) -> Vec<&'static str> {
+    term.console().handler().unwrap().shell().context().clone()
+}
+
+fn idle(term: &mut Harness<Script>, times: usize) {
+    for _ in 0..times {
+        let _ = term.console().input();
+    }
+}
+
+fn no_clock() -> u32 {
+    0
+}
+
+#[test]
+fn commands_separated_by_semicolons() {
+    let mut term = harness(no_clock);
+    term.keys("led on; led off ;;led on\r");
+    assert_eq!(log(&mut term), ["on", "off", "on"]);
+    assert_eq!(term.line(), ">");
+}
+
+#[test]
+fn sleep_does_not_block_the_console() {
+    static NOW: AtomicU32 = AtomicU32::new(u32::MAX - 10);
+    let mut term = harness(|| NOW.load(Ordering::Relaxed));
+    term.keys("led on; sleep 100; led off\r");
+    assert_eq!(log(&mut term), ["on"]);
+    assert!(term.console().is_running());
+    NOW.fetch_add(99, Ordering::Relaxed);
+    idle(&mut term, 3);
+    assert_eq!(log(&mut term), ["on"]);
+    NOW.fetch_add(1, Ordering::Relaxed);
+    idle(&mut term, 1);
+    assert_eq!(log(&mut term), ["on", "off"]);
+    assert!(!term.console().is_running());
+    assert_eq!(term.line(), ">");
+}
+
+#[test]
+fn aliases_and_repeat() {
+    let mut term = harness(no_clock);
+    term.keys("alias blink \"led on; led off\"\r");
+    term.keys("alias\r");
+    assert!(term.screen().text().contains(&String::from("blink = led on; led off")));
+    term.keys("repeat 2 blink; led on\r");
+    idle(&mut term, 4);
+    assert_eq!(log(&mut term), ["on", "off", "on", "on", "off", "on"]);
+    term.keys("unalias blink\rblink\r");
+    assert_eq!(term.screen().text().last().unwrap(), ">");
+    assert!(term.screen().text().contains(&String::from("blink: unknown command")));
+}
+
+#[test]
+fn tables_are_bounded() {
+    let mut term = harness(no_clock);
+    term.keys("alias a \"led on\";alias b \"led off\";alias c x\r");
+    assert!(term.screen().text().contains(&String::from("alias: alias table full")));
+    term.keys("led on; led off; led on; led off; led on; led off\r");
+    assert!(term.screen().text().contains(&String::from("line too long")));
+    term.keys("a; b\r");
+    assert_eq!(log(&mut term), ["on", "off"]);
+}
+
+#[test]
+fn errors_and_ctrl_c_stop_the_script() {
+    static NOW: AtomicU32 = AtomicU32::new(0);
+    let mut term = harness(|| NOW.load(Ordering::Relaxed));
+    term.keys("led on; led maybe; led off\r");
+    assert_eq!(log(&mut term), ["on"]);
+    assert!(term.screen().text().contains(&String::from("led: invalid argument #1")));
+    term.keys("repeat 1000 led on; sleep 10\r");
+    NOW.fetch_add(10, Ordering::Relaxed);
+    idle(&mut term, 2);
+    term.keys("\x03");
+    NOW.fetch_add(10, Ordering::Relaxed);
+    idle(&mut term, 2);
+    assert_eq!(log(&mut term), ["on", "on", "on"]);
+    assert!(!term.console().is_running());
+    assert_eq!(term.line(), ">");
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write;
+
+use embedded_lib::command::{Args, Command, CommandError, SharedShell};
+use embedded_lib::console::Output;
+use embedded_lib::testing::Harness;
+
+fn inc(count: &mut u32, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    args.finish()?;
+    *count += 1;
+    let _ = writeln!(output, "count {}", count);
+    Ok(())
+}
+
+fn wait(_: &mut u32, args: &mut Args, output: &mut Output) -> Result<(), CommandError> {
+    args.finish()?;
+    output.keep_running();
+    Ok(())
+}
+
+fn wait_poll(count: &mut u32, output: &mut Output) -> Result<(), CommandError> {
+    if *count < 3 {
+        output.keep_running();
+    }
+    else {
+        let _ = writeln!(output, "reached {}", count);
+    }
+    Ok(())
+}
+
+const COMMANDS: [Command<u32>; 2] = [
+    Command::new("inc", "inc", inc),
+    Command::new("wait", "wait for 3", wait).with_poll(wait_poll),
+];
+
+#[test]
+fn sessions_share_the_context_but_not_the_output() {
+    let shell = SharedShell::new(&COMMANDS, 0);
+    let mut uart = Harness::with_handler("uart> ", 64, 24, 80, shell.session());
+    let mut usb = Harness::with_handler("usb> ", 64, 24, 80, shell.session());
+
+    uart.keys("in");
+    usb.keys("inc\r");
+    uart.keys("c\r");
+    assert_eq!(usb.screen().text(), ["usb> inc", "count 1", "usb>"]);
+    assert_eq!(uart.screen().text(), ["uart> inc", "count 2", "uart>"]);
+    assert_eq!(*shell.context(), 2);
+}
+
+#[test]
+fn running_command_stays_with_its_session() {
+    let shell = SharedShell::new(&COMMANDS, 0);
+    let mut uart = Harness::with_handler("uart> ", 64, 24, 80, shell.session());
+    let mut usb = Harness::with_handler("usb> ", 64, 24, 80, shell.session());
+
+    uart.keys("wait\r");
+    let _ = uart.console().input();
+    assert!(uart.console().is_running());
+    usb.keys("inc\rinc\r");
+    assert!(!usb.console().is_running());
+    let _ = uart.console().input();
+    assert!(uart.console().is_running());
+    usb.keys("inc\r");
+    let _ = uart.console().input();
+    assert!(!uart.console().is_running());
+    assert_eq!(uart.screen().text(), ["uart> wait", "reached 3", "uart>"]);
+    assert_eq!(usb.screen().text().last().unwrap(), "usb>");
+}
@@ -0,0 +1,240 @@
+use std::alloc::{alloc_zeroed, Layout};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use embedded_lib::shared_ringbuffer::{CacheCoherency, CriticalSection, LayoutError, SharedRingBuffer, SharedRingBufferError, SharedRingBufferWithCS};
+use embedded_lib::spsc_ringbuffer::SpscSharedRingBuffer;
+
+// Memory both sides of a ring buffer use, aligned to a cache
+// line and never freed.
+fn shared_memory(size: u32) -> *mut u32 {
+    let layout = Layout::from_size_align(size as usize, 32).unwrap();
+    unsafe { alloc_zeroed(layout) as *mut u32 }
+}
+
+struct Lock;
+
+impl Drop for Lock {
+    fn drop(&mut self) {}
+}
+
+struct NoCriticalSection;
+
+impl CriticalSection for NoCriticalSection {
+    fn lock(&self) -> Result<impl Drop, SharedRingBufferError> {
+        Ok(Lock)
+    }
+}
+
+#[test]
+fn read_returns_the_bytes_written() {
+    type Buffer = SharedRingBuffer<16, 4>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0) }.unwrap();
+    let mut message = [0xffu8; 32];
+    assert!(matches!(buffer.read(&mut message), Err(SharedRingBufferError::NoData)));
+
+    buffer.write(b"help").ok().unwrap();
+    buffer.write(b"led on").ok().unwrap();
+    buffer.write(b"").ok().unwrap();
+    assert!(matches!(buffer.write(b"full"), Err(SharedRingBufferError::NoSpace)));
+
+    let len = buffer.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"help");
+    let len = buffer.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"led on");
+    assert_eq!(buffer.read(&mut message).ok(), Some(0));
+    assert!(matches!(buffer.read(&mut message), Err(SharedRingBufferError::NoData)));
+}
+
+#[test]
+fn short_destination_gets_the_start_of_the_message() {
+    type Buffer = SharedRingBuffer<16, 4>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0) }.unwrap();
+    buffer.write(b"0123456789").ok().unwrap();
+    let mut message = [0u8; 4];
+    assert_eq!(buffer.read(&mut message).ok(), Some(4));
+    assert_eq!(&message, b"0123");
+}
+
+#[test]
+fn with_cs_read_returns_the_bytes_written() {
+    type Buffer = SharedRingBufferWithCS<16, 3, NoCriticalSection>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0, NoCriticalSection) }.unwrap();
+    // the oldest message is overwritten when the buffer is full
+    for message in [&b"one"[..], b"two", b"three"] {
+        buffer.write(message).ok().unwrap();
+    }
+    let mut message = [0u8; 16];
+    let len = buffer.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"two");
+    let len = buffer.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"three");
+    assert!(matches!(buffer.read(&mut message), Err(SharedRingBufferError::NoData)));
+}
+
+// Fails to lock while the other core holds the semaphore.
+struct Semaphore(&'static AtomicBool);
+
+impl CriticalSection for Semaphore {
+    fn lock(&self) -> Result<impl Drop, SharedRingBufferError> {
+        if self.0.load(Ordering::Relaxed) { Err(SharedRingBufferError::CantLock) } else { Ok(Lock) }
+    }
+}
+
+#[test]
+fn with_cs_nothing_is_read_without_the_lock() {
+    type Buffer = SharedRingBufferWithCS<16, 3, Semaphore>;
+    let taken: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0, Semaphore(taken)) }.unwrap();
+    buffer.write(b"one").ok().unwrap();
+    taken.store(true, Ordering::Relaxed);
+    let mut message = [0u8; 16];
+    assert!(matches!(buffer.write(b"two"), Err(SharedRingBufferError::CantLock)));
+    assert!(matches!(buffer.read(&mut message), Err(SharedRingBufferError::CantLock)));
+    assert!(matches!(buffer.read_message(&mut message), Err(SharedRingBufferError::CantLock)));
+    taken.store(false, Ordering::Relaxed);
+    let len = buffer.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"one");
+}
+
+#[test]
+fn message_larger_than_a_slot_is_refused() {
+    type Buffer = SharedRingBuffer<4, 4>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0) }.unwrap();
+    assert!(matches!(buffer.write(b"01234"), Err(SharedRingBufferError::MessageTooLarge)));
+    buffer.write(b"0123").ok().unwrap();
+    let mut message = [0u8; 8];
+    assert_eq!(buffer.read_message(&mut message).ok(), Some(4));
+    assert!(matches!(buffer.read(&mut message), Err(SharedRingBufferError::NoData)));
+}
+
+#[test]
+fn fragmented_message_is_put_together() {
+    type Buffer = SharedRingBuffer<4, 4>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0) }.unwrap();
+    buffer.set_fragmentation(true);
+    // three slots at most
+    assert!(matches!(buffer.write(b"0123456789abc"), Err(SharedRingBufferError::MessageTooLarge)));
+    buffer.write(b"0123456789").ok().unwrap();
+    assert!(matches!(buffer.write(b"x"), Err(SharedRingBufferError::NoSpace)));
+    let mut message = [0u8; 16];
+    assert_eq!(buffer.read_message(&mut message).ok(), Some(10));
+    assert_eq!(&message[..10], b"0123456789");
+
+    buffer.write(b"led on").ok().unwrap();
+    buffer.write(b"").ok().unwrap();
+    // a destination too short for the whole message drops it
+    assert!(matches!(buffer.read_message(&mut message[..5]), Err(SharedRingBufferError::MessageTooLarge)));
+    assert_eq!(buffer.read_message(&mut message).ok(), Some(0));
+    assert!(matches!(buffer.read_message(&mut message), Err(SharedRingBufferError::NoData)));
+}
+
+#[test]
+fn with_cs_overwritten_start_is_a_partial_message() {
+    type Buffer = SharedRingBufferWithCS<4, 4, NoCriticalSection>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0, NoCriticalSection) }.unwrap();
+    buffer.set_fragmentation(true);
+    buffer.write(b"abcdefgh").ok().unwrap();
+    buffer.write(b"ijkl").ok().unwrap();
+    // takes the slot of "abcd"
+    buffer.write(b"mn").ok().unwrap();
+    let mut message = [0u8; 16];
+    assert!(matches!(buffer.read_message(&mut message), Err(SharedRingBufferError::PartialMessage)));
+    let len = buffer.read_message(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"ijkl");
+    let len = buffer.read_message(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"mn");
+    assert!(matches!(buffer.read_message(&mut message), Err(SharedRingBufferError::NoData)));
+}
+
+#[test]
+fn spsc_read_returns_the_bytes_written() {
+    type Buffer = SpscSharedRingBuffer<4, 3>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut writer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 4) }.unwrap();
+    let mut reader = unsafe { Buffer::attach(memory, Buffer::MEMORY_SIZE) }.unwrap();
+    assert_eq!(reader.owner(), 4);
+    assert!(matches!(writer.write(b"01234"), Err(SharedRingBufferError::MessageTooLarge)));
+    writer.write(b"led").ok().unwrap();
+    writer.write(b"on").ok().unwrap();
+    assert!(matches!(writer.write(b"off"), Err(SharedRingBufferError::NoSpace)));
+    let mut message = [0u8; 4];
+    let len = reader.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"led");
+    writer.write(b"off").ok().unwrap();
+    let len = reader.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"on");
+    let len = reader.read(&mut message).ok().unwrap();
+    assert_eq!(&message[..len], b"off");
+    assert!(matches!(reader.read(&mut message), Err(SharedRingBufferError::NoData)));
+}
+
+// Records the cache maintenance instead of doing it.
+static MAINTENANCE: Mutex<Vec<(&str, usize, usize)>> = Mutex::new(Vec::new());
+
+struct RecordedCache;
+
+impl CacheCoherency for RecordedCache {
+    const LINE_SIZE: usize = 32;
+
+    fn clean(address: usize, len: usize) {
+        MAINTENANCE.lock().unwrap().push(("clean", address, len));
+    }
+
+    fn invalidate(address: usize, len: usize) {
+        MAINTENANCE.lock().unwrap().push(("invalidate", address, len));
+    }
+}
+
+fn maintenance() -> Vec<(&'static str, usize, usize)> {
+    std::mem::take(&mut MAINTENANCE.lock().unwrap())
+}
+
+#[test]
+fn slots_are_cleaned_after_writing_and_invalidated_before_reading() {
+    type Buffer = SharedRingBuffer<20, 4, RecordedCache>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    let mut buffer = unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 0) }.unwrap();
+    let (indices, slot) = (memory as usize, memory as usize + 128);
+    let slot_size = (Buffer::MEMORY_SIZE as usize - 512) / 4;
+    assert_eq!(slot_size % 32, 0);
+    assert_eq!(maintenance(), [("clean", indices, Buffer::MEMORY_SIZE as usize), ("clean", indices + 64, 32)]);
+
+    buffer.write(b"hello").ok().unwrap();
+    assert_eq!(maintenance(), [("invalidate", indices, 64), ("clean", slot, slot_size), ("clean", indices, 64)]);
+    let mut message = [0u8; 20];
+    assert_eq!(buffer.read(&mut message).ok(), Some(5));
+    assert_eq!(maintenance(), [("invalidate", indices, 64), ("invalidate", slot, slot_size), ("clean", indices, 64)]);
+}
+
+#[test]
+fn attach_checks_what_was_created() {
+    type Buffer = SharedRingBuffer<16, 4>;
+    let memory = shared_memory(Buffer::MEMORY_SIZE);
+    assert_eq!(unsafe { Buffer::attach(memory, Buffer::MEMORY_SIZE) }.err(), Some(LayoutError::NotCreated));
+    assert_eq!(unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE - 1, 7) }.err(), Some(LayoutError::MemoryTooSmall(Buffer::MEMORY_SIZE)));
+    assert_eq!(unsafe { Buffer::create(memory.wrapping_add(1), Buffer::MEMORY_SIZE, 7) }.err(), Some(LayoutError::Unaligned));
+
+    unsafe { Buffer::create(memory, Buffer::MEMORY_SIZE, 7) }.unwrap().write(b"hello").ok().unwrap();
+    assert_eq!(unsafe { SharedRingBuffer::<8, 4>::attach(memory, Buffer::MEMORY_SIZE) }.err(),
+               Some(LayoutError::Geometry { slot_size: 16, slots: 4 }));
+    assert_eq!(unsafe { SpscSharedRingBuffer::<16, 4>::attach(memory, Buffer::MEMORY_SIZE) }.err(), Some(LayoutError::NotCreated));
+    {
+        let mut peer = unsafe { Buffer::attach(memory, Buffer::MEMORY_SIZE) }.unwrap();
+        assert_eq!(peer.owner(), 7);
+        let mut message = [0u8; 16];
+        let len = peer.read(&mut message).ok().unwrap();
+        assert_eq!(&message[..len], b"hello");
+    }
+
+    // the version follows magic in the header, on the third cache line
+    unsafe { *memory.add(17) = 2 };
+    assert_eq!(unsafe { Buffer::attach(memory, Buffer::MEMORY_SIZE) }.err(), Some(LayoutError::Version(2)));
+}
@@ -0,0 +1,98 @@
+#!/usr/bin/env python3
+# Writes the register table used by the regdump command from an SVD file.
+#
+#   tools/svd2regs.py STM32H755_CM7.svd HSEM RCC EXTI TIM2 TIM3 ... > src/stm32h7/registers.rs
+#
+# Peripherals derived from another one share its register list.
+
+import re
+import sys
+import xml.etree.ElementTree as ET
+
+# Registers whose read changes the state, regdump does not read them.
+READ_SIDE_EFFECT = {
+    'HSEM': [r'RLR\d+'],
+    # reads or writes the register DCR points to and moves on to the next
+    'TIM2': ['DMAR'],
+    'USART1': ['RDR'],
+}
+
+ACCESS = {
+    'read-write': 'ReadWrite',
+    'read-only': 'ReadOnly',
+    'write-only': 'WriteOnly',
+    'writeOnce': 'WriteOnly',
+    'read-writeOnce': 'ReadWrite',
+}
+
+
+def number(text):
+    return int(text, 0)
+
+
+def registers(peripheral, group):
+    result = []
+    default_access = peripheral.findtext('access', 'read-write')
+    for register in peripheral.iter('register'):
+        # another view of a register already listed, e.g. CCMR1_Input
+        if register.find('alternateRegister') is not None:
+            continue
+        name = register.findtext('name')
+        offset = number(register.findtext('addressOffset'))
+        access = ACCESS[register.findtext('access', default_access)]
+        dim = register.findtext('dim')
+        if dim is None:
+            names = [(name, offset)]
+        else:
+            increment = number(register.findtext('dimIncrement'))
+            index = register.findtext('dimIndex')
+            indices = index.split(',') if index else [str(i) for i in range(number(dim))]
+            names = [(name.replace('%s', n), offset + i * increment) for i, n in enumerate(indices)]
+        for name, offset in names:
+            if any(re.fullmatch(p, name) for p in READ_SIDE_EFFECT.get(group, [])):
+                result.append((name, offset, 'ReadSideEffect'))
+            else:
+                result.append((name, offset, access))
+    return sorted(result, key=lambda register: register[1])
+
+
+def main():
+    svd = ET.parse(sys.argv[1]).getroot()
+    wanted = sys.argv[2:]
+    peripherals = {p.findtext('name'): p for p in svd.iter('peripheral')}
+
+    print('// Generated by tools/svd2regs.py from %s, do not edit.' % sys.argv[1].split('/')[-1])
+    print()
+    print('use crate::memory::{Access::*, Peripheral, Register};')
+
+    tables = {}
+    for name in wanted:
+        peripheral = peripherals[name]
+        group = peripheral.get('derivedFrom', name)
+        if group in tables:
+            continue
+        table = registers(peripherals[group], group)
+        tables[group] = table
+        print()
+        print('static %s_REGISTERS: [Register; %d] = [' % (group, len(table)))
+        for register, offset, access in table:
+            print('    Register::new("%s", 0x%03x, %s),' % (register, offset, access))
+        print('];')
+
+    print()
+    print('pub static PERIPHERALS: [Peripheral; %d] = [' % len(wanted))
+    for name in wanted:
+        peripheral = peripherals[name]
+        group = peripheral.get('derivedFrom', name)
+        base = number(peripheral.findtext('baseAddress'))
+        block = peripheral.find('addressBlock')
+        if block is None:
+            block = peripherals[group].find('addressBlock')
+        size = number(block.findtext('size'))
+        print('    Peripheral { name: "%s", base: 0x%04x_%04x, size: 0x%x, registers: &%s_REGISTERS },'
+              % (name, base >> 16, base & 0xffff, size, group))
+    print('];')
+
+
+if __name__ == '__main__':
+    main()
@@ -37,13 +37,24 @@ use stm32h7xx_hal::{pac, interrupt, rcc, pwr, timer, hsem, exti, block, prelude:
 use stm32h7xx_hal::usb_hs::{UsbBus, USB2};
 use stm32h7xx_hal::time::MilliSeconds;
 use log::{info,debug};
-use embedded_lib::console;
+use embedded_lib::{console, history};
+use embedded_lib::command::{Args, Command, CommandError, SharedShell};
+use embedded_lib::memory::{self, MemoryMap, Peripheral, Region};
+use embedded_lib::stm32h7;
+use embedded_lib::transport::Transport;
 
 use usb_device::prelude::*;
+use usb_device::UsbError;
 
 #[link_section = ".sram2"]
 static mut CONSOLE_BUFFER: [u8; 1024] = [0u8; 1024];
 #[link_section = ".sram2"]
+static mut USB_CONSOLE_BUFFER: [u8; 1024] = [0u8; 1024];
+#[link_section = ".sram2"]
+static mut HISTORY_BUFFER: [u8; 128*9] = [0u8; 128*9];
+#[link_section = ".sram2"]
+static mut USB_HISTORY_BUFFER: [u8; 128*9] = [0u8; 128*9];
+#[link_section = ".sram2"]
 static mut EP_MEMORY: MaybeUninit<[u32; 1024]> = MaybeUninit::uninit();
 
 #[macro_use]
@@ -57,6 +68,87 @@ static TIMER: cm_interrupt::Mutex<RefCell<Option<timer::Timer<pac::TIM3>>>> =
 static LED_BLINK: cm_interrupt::Mutex<RefCell<bool>> =
     cm_interrupt::Mutex::new(RefCell::new(false));
 
+// State the commands work on, shared by the UART and the USB console.
+struct Board {
+    blink: bool,
+}
+
+impl MemoryMap for Board {
+    fn regions(&self) -> &[Region] {
+        &stm32h7::CM4_REGIONS
+    }
+
+    fn peripherals(&self) -> &[Peripheral] {
+        &stm32h7::PERIPHERALS
+    }
+}
+
+fn cmd_blink(board: &mut Board, args: &mut Args, output: &mut console::Output) -> Result<(), CommandError> {
+    match args.optional::<bool>()? {
+        Some(blink) => board.blink = blink,
+        None => { let _ = writeln!(output, "{}", if board.blink { "on" } else { "off" }); },
+    }
+    args.finish()
+}
+
+fn cmd_uptime(_: &mut Board, args: &mut Args, output: &mut console::Output) -> Result<(), CommandError> {
+    args.finish()?;
+    let _ = writeln!(output, "{} s", SEC_COUNTER.load(Ordering::Relaxed));
+    Ok(())
+}
+
+static COMMANDS: [Command<Board>; 5] = [
+    Command::new("blink", "blink [on|off]  blink LD2", cmd_blink),
+    Command::new("uptime", "uptime  seconds since start", cmd_uptime),
+    memory::md(),
+    memory::mw(),
+    memory::regdump(),
+];
+
+// USB CDC port as a console transport. The device is polled on every
+// read, output is dropped while no terminal has the port open.
+struct UsbSerial<'a> {
+    device: UsbDevice<'a, UsbBus<USB2>>,
+    serial: usbd_serial::SerialPort<'a, UsbBus<USB2>>,
+}
+
+impl UsbSerial<'_> {
+    fn is_open(&self) -> bool {
+        self.device.state() == UsbDeviceState::Configured && self.serial.dtr()
+    }
+}
+
+impl Transport for UsbSerial<'_> {
+    type Error = UsbError;
+
+    fn read(&mut self) -> Result<Option<u8>, UsbError> {
+        if !self.device.poll(&mut [&mut self.serial]) {
+            return Ok(None);
+        }
+        let mut c = [0u8; 1];
+        match self.serial.read(&mut c) {
+            Ok(1) => Ok(Some(c[0])),
+            Ok(_) | Err(UsbError::WouldBlock) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), UsbError> {
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            if !self.is_open() {
+                return Ok(());
+            }
+            match self.serial.write(bytes) {
+                Ok(len) => bytes = &bytes[len..],
+                Err(UsbError::WouldBlock) => { self.device.poll(&mut [&mut self.serial]); },
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
 #[entry]
 fn main() -> ! {
     utilities::logger::init();
@@ -137,8 +229,8 @@ fn main() -> ! {
 
     let usb_bus = UsbBus::new(usb, unsafe { EP_MEMORY.assume_init_mut() });
 
-    let mut serial = usbd_serial::SerialPort::new(&usb_bus);
-    let mut usb_dev =
+    let serial = usbd_serial::SerialPort::new(&usb_bus);
+    let usb_dev =
         UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
             .strings(&[usb_device::device::StringDescriptors::default()
                 .manufacturer("Fake company")
@@ -161,10 +253,12 @@ fn main() -> ! {
         NVIC::unmask::<stm32h7xx_hal::interrupt>(interrupt::TIM3);
     }
 
+    let shell = SharedShell::new(&COMMANDS, Board { blink: true });
+
     let mut console =
         unsafe {
             console::Console::new(
-                &mut CONSOLE_BUFFER,
+                &mut *core::ptr::addr_of_mut!(CONSOLE_BUFFER),
                 "cm4> ",
                 move || {
                     match usart_rx.read() {
@@ -177,8 +271,16 @@ fn main() -> ! {
                 move |c| {
                     block!(usart_tx.write(c)).ok();
                 },
-                None::<fn (&str)>)
+                Some(shell.session()))
         };
+    console.set_history(unsafe { history::History::new(&mut *core::ptr::addr_of_mut!(HISTORY_BUFFER), 8) });
+
+    let mut usb_console = console::Console::with_transport(
+        unsafe { &mut *core::ptr::addr_of_mut!(USB_CONSOLE_BUFFER) },
+        "cm4(usb)> ",
+        UsbSerial { device: usb_dev, serial },
+        Some(shell.session()));
+    usb_console.set_history(unsafe { history::History::new(&mut *core::ptr::addr_of_mut!(USB_HISTORY_BUFFER), 8) });
 
     // Configure PE1 as output.
     let mut led = gpioe.pe1.into_push_pull_output();
@@ -186,37 +288,23 @@ fn main() -> ! {
     let _ = writeln!(console,"start blinking LD2                  \r");
 
     let mut prev_blink = false;
+    let mut usb_open = false;
     loop {
 
-        if usb_dev.poll(&mut [&mut serial]) {
-            let mut buf = [0u8; 64];
-            match serial.read(&mut buf) {
-                Ok(count) if count > 0 => {
-                    // Echo back in upper case
-                    for c in buf[0..count].iter_mut() {
-                        if 0x61 <= *c && *c <= 0x7a {
-                            *c &= !0x20;
-                        }
-                    }
+        let _ = usb_console.input();
 
-                    let mut write_offset = 0;
-                    while write_offset < count {
-                        match serial.write(&buf[write_offset..count]) {
-                            Ok(len) if len > 0 => {
-                                write_offset += len;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                _ => {}
-            }
+        // the prompt was dropped until a terminal opened the port
+        let open = usb_console.transport().is_open();
+        if open && !usb_open {
+            let _ = usb_console.redraw();
         }
+        usb_open = open;
 
         let mut update = false;
+        let blink = shell.context().blink;
 
         cm_interrupt::free(|cs| {
-            let current = *LED_BLINK.borrow(cs).borrow();
+            let current = *LED_BLINK.borrow(cs).borrow() && blink;
             update = prev_blink != current;
             if update {
                  if current {
@@ -228,7 +316,7 @@ fn main() -> ! {
             prev_blink = current;
         });
 
-        console.input();
+        let _ = console.input();
 
     }
 }
@@ -228,7 +228,7 @@ fn main() -> ! {
             prev_blink = current;
         });
 
-        console.input();
+        let _ = console.input();
 
     }
 }
@@ -152,7 +152,7 @@ fn main() -> ! {
             prev_blink = current;
         });
 
-        console.input();
+        let _ = console.input();
     }
 }
 
@@ -10,7 +10,7 @@
 use core::{
     fmt::Write,
     cell::RefCell,
-    sync::atomic::{AtomicU32, AtomicBool, Ordering},
+    sync::atomic::{AtomicU32, Ordering},
 };
 
 use cortex_m_rt::entry;
@@ -19,25 +19,23 @@ use cortex_m::peripheral::NVIC;
 use stm32h7xx_hal::{pac, interrupt, rcc, pwr, timer, hsem, exti, block, prelude::* };
 use stm32h7xx_hal::time::MilliSeconds;
 use log::{info,debug};
-use embedded_lib::{console,shared_ringbuffer};
+use embedded_lib::{console,shared_ringbuffer,spsc_ringbuffer};
 
 #[link_section = ".sram2"]
 static mut CONSOLE_BUFFER: [u8; 1024] = [0u8; 1024];
 
 const CM7_TO_CM4_SHARED_RINGBUFFER: *mut u32 = 0x10040000 as *mut u32; // in D2 Domain, Write-Through
-const CM7_TO_CM4_SHARED_RINGBUFFER_SIZE: u32 = 512+1024*8; //
+const CM7_TO_CM4_SHARED_RINGBUFFER_SIZE: u32 = spsc_ringbuffer::SpscSharedRingBuffer::<1024,8>::MEMORY_SIZE;
 const CM4_TO_CM7_SHARED_RINGBUFFER: *mut u32 = 0x10042400 as *mut u32; // in D2 Domain, Write-Through
-const CM4_TO_CM7_SHARED_RINGBUFFER_SIZE: u32 = 512+1024*8; //
+const CM4_TO_CM7_SHARED_RINGBUFFER_SIZE: u32 = shared_ringbuffer::SharedRingBufferWithCS::<1024,8,HardwareCriticalSection>::MEMORY_SIZE;
+const CM4_CORE_ID: u32 = 1; // as the HSEM knows it, recorded as owner of both ring buffers
 
 #[macro_use]
 mod utilities;
 
 static SEC_COUNTER: AtomicU32 = AtomicU32::new(0);
-static MESSAGE_NOTIFY: AtomicBool = AtomicBool::new(false);
 static HSEM_CH0: cm_interrupt::Mutex<RefCell<Option<hsem::Sema<0>>>> =
     cm_interrupt::Mutex::new(RefCell::new(None));
-static HSEM_CH2: cm_interrupt::Mutex<RefCell<Option<hsem::Sema<2>>>> =
-    cm_interrupt::Mutex::new(RefCell::new(None));
 static TIMER: cm_interrupt::Mutex<RefCell<Option<timer::Timer<pac::TIM3>>>> =
     cm_interrupt::Mutex::new(RefCell::new(None));
 static LED_BLINK: cm_interrupt::Mutex<RefCell<bool>> =
@@ -116,11 +114,16 @@ fn main() -> ! {
     let clocks = rcc.get_frozen_core_clocks().expect("could not get clocks");
 
     let mut sem1 = hsem.sema1();
-    let mut cm7_to_cm4_shared_ringbuffer = unsafe {
-        let ptr : *mut u8 = CM7_TO_CM4_SHARED_RINGBUFFER as *mut u8;
-        ptr.write_bytes(0, CM7_TO_CM4_SHARED_RINGBUFFER_SIZE as usize);
-        shared_ringbuffer::SharedRingBuffer::<1024,8>::assign(CM7_TO_CM4_SHARED_RINGBUFFER,
-                                                              CM7_TO_CM4_SHARED_RINGBUFFER_SIZE)
+    // cm7 attaches to the ring buffers once sem1 is released below
+    let mut cm7_to_cm4_shared_ringbuffer = match unsafe {
+        spsc_ringbuffer::SpscSharedRingBuffer::<1024,8>::create(CM7_TO_CM4_SHARED_RINGBUFFER,
+                                                                CM7_TO_CM4_SHARED_RINGBUFFER_SIZE,
+                                                                CM4_CORE_ID)
+    } {
+        Ok(b) => b,
+        Err(err) => {
+            panic!("failed to create cm7 to cm4 ring buffer. {}", err);
+        }
     };
 
     let hwcs = HardwareCriticalSection {
@@ -128,24 +131,22 @@ fn main() -> ! {
         sem: RefCell::new(hsem.sema3())
     };
 
-    let mut cm4_to_cm7_shared_ringbuffer = unsafe {
-        let ptr : *mut u8 = CM4_TO_CM7_SHARED_RINGBUFFER as *mut u8;
-        ptr.write_bytes(0, CM4_TO_CM7_SHARED_RINGBUFFER_SIZE as usize);
+    let mut cm4_to_cm7_shared_ringbuffer = match unsafe {
         shared_ringbuffer::SharedRingBufferWithCS::<1024,8, HardwareCriticalSection>
-            ::assign(CM4_TO_CM7_SHARED_RINGBUFFER,
+            ::create(CM4_TO_CM7_SHARED_RINGBUFFER,
                      CM4_TO_CM7_SHARED_RINGBUFFER_SIZE,
+                     CM4_CORE_ID,
                      hwcs)
+    } {
+        Ok(b) => b,
+        Err(err) => {
+            panic!("failed to create cm4 to cm7 ring buffer. {}", err);
+        }
     };
 
     sem1.fast_take();
     sem1.release(0);
 
-    let sem2 = hsem.sema2();
-    cm_interrupt::free(|cs| {
-        HSEM_CH2.borrow(cs).replace(Some(sem2));
-        HSEM_CH2.borrow(cs).borrow_mut().as_mut().unwrap().enable_irq();
-    });
-
     // GPIOD was reseted by CM7
     let gpiod = dp.GPIOD.split_without_reset(prec.GPIOD);
     let gpioe = dp.GPIOE.split(prec.GPIOE);
@@ -193,7 +194,9 @@ fn main() -> ! {
             },
             Some(move |command:&str| {
                 //debug!("send {} <{}>", command.len(), command);
-                let _ = cm4_to_cm7_shared_ringbuffer.write(command.as_bytes());
+                if let Err(e) = cm4_to_cm7_shared_ringbuffer.write(command.as_bytes()) {
+                    debug!("send error: {}", e);
+                }
             }))
         };
 
@@ -220,25 +223,18 @@ fn main() -> ! {
             prev_blink = current;
         });
 
-        console.input();
-
-        if let Ok(notify) = MESSAGE_NOTIFY.fetch_update(Ordering::SeqCst,
-                                                        Ordering::SeqCst,
-                                                        |notify| if notify {
-                                                            Some(false)
-                                                        }
-                                                        else {
-                                                            None
-                                                        }){
-            if notify {
-                let mut recvbuf = [0u8;1024];
-                match cm7_to_cm4_shared_ringbuffer.read(&mut recvbuf) {
-                    Ok(readsize) => {
-                        let _ = write!(console,">cm7> {}\r\n", core::str::from_utf8(&recvbuf).unwrap());
-                    },
-                    Err(e) => { debug!("read error: {}", e); }
-                };
-            }
+        let _ = console.input();
+
+        {
+            let mut recvbuf = [0u8;1024];
+            match cm7_to_cm4_shared_ringbuffer.read(&mut recvbuf) {
+                Ok(readsize) => match core::str::from_utf8(&recvbuf[..readsize]) {
+                    Ok(message) => { let _ = console.write_above(format_args!(">cm7> {}", message)); },
+                    Err(_) => debug!("message is not UTF-8"),
+                },
+                Err(shared_ringbuffer::SharedRingBufferError::NoData) => {},
+                Err(e) => { debug!("read error: {}", e); }
+            };
         }
     }
 }
@@ -252,13 +248,6 @@ fn HSEM1() {
         if sem0.status_irq() {
             sem0.clear_irq()
         };
-        let mut binding = HSEM_CH2.borrow(cs).borrow_mut();
-        if let Some(sem2) = binding.as_mut(){
-            if sem2.status_irq() {
-                MESSAGE_NOTIFY.store(true,Ordering::SeqCst);
-                sem2.clear_irq()
-            };
-        };
     });
 }
 
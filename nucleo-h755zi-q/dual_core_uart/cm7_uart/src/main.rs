@@ -14,7 +14,7 @@ use cortex_m::peripheral::NVIC;
 use stm32h7xx_hal::{pac, interrupt, timer, block, hsem, prelude::*};
 use stm32h7xx_hal::time::MilliSeconds;
 use log::{info,debug};
-use embedded_lib::{console,shared_ringbuffer};
+use embedded_lib::{console,history,shared_ringbuffer,spsc_ringbuffer};
 
 #[macro_use]
 mod utilities;
@@ -59,11 +59,13 @@ static LED_BLINK: cm_interrupt::Mutex<RefCell<bool>> =
 
 #[link_section = ".axisram"]
 static mut CONSOLE_BUFFER: [u8; 1024] = [0u8; 1024];
+#[link_section = ".axisram"]
+static mut HISTORY_BUFFER: [u8; 128*9] = [0u8; 128*9];
 
 const CM7_TO_CM4_SHARED_RINGBUFFER: *mut u32 = 0x10040000 as *mut u32; // in D2 Domain, Write-Through
-const CM7_TO_CM4_SHARED_RINGBUFFER_SIZE: u32 = 512+1024*8;
+const CM7_TO_CM4_SHARED_RINGBUFFER_SIZE: u32 = spsc_ringbuffer::SpscSharedRingBuffer::<1024,8>::MEMORY_SIZE;
 const CM4_TO_CM7_SHARED_RINGBUFFER: *mut u32 = 0x10042400 as *mut u32; // in D2 Domain, Write-Through
-const CM4_TO_CM7_SHARED_RINGBUFFER_SIZE: u32 = 512+1024*8; //
+const CM4_TO_CM7_SHARED_RINGBUFFER_SIZE: u32 = shared_ringbuffer::SharedRingBufferWithCS::<1024,8,HardwareCriticalSection>::MEMORY_SIZE;
 
 #[allow(dead_code)]
 fn type_of<T>(_: &T) -> &'static str {
@@ -103,7 +105,6 @@ fn main() -> ! {
     let mut hsem = dp.HSEM.hsem_without_reset(ccdr.peripheral.HSEM);
     let mut sem0 = hsem.sema0();
     let mut sem1 = hsem.sema1();
-    let mut sem2 = hsem.sema2();
     sem1.enable_irq();
 
     info!("cm7# wake up cm4.");
@@ -122,10 +123,17 @@ fn main() -> ! {
     }
 
     info!("setup shared ringbuffer");
-    let mut cm7_to_cm4_shared_ringbuffer = unsafe {
-        shared_ringbuffer::SharedRingBuffer::<1024,8>::assign(CM7_TO_CM4_SHARED_RINGBUFFER,
-                                                              CM7_TO_CM4_SHARED_RINGBUFFER_SIZE)
+    // created by cm4 before it released sem1
+    let mut cm7_to_cm4_shared_ringbuffer = match unsafe {
+        spsc_ringbuffer::SpscSharedRingBuffer::<1024,8>::attach(CM7_TO_CM4_SHARED_RINGBUFFER,
+                                                                CM7_TO_CM4_SHARED_RINGBUFFER_SIZE)
+    } {
+        Ok(b) => b,
+        Err(err) => {
+            panic!("failed to attach cm7 to cm4 ring buffer. {}", err);
+        }
     };
+    debug!("ring buffers created by core {}", cm7_to_cm4_shared_ringbuffer.owner());
 
     let (sem3op, _) = hsem.sema3().split();
     let hwcs = HardwareCriticalSection {
@@ -133,11 +141,16 @@ fn main() -> ! {
         sem: RefCell::new(sem3op)
     };
 
-    let mut cm4_to_cm7_shared_ringbuffer = unsafe {
+    let mut cm4_to_cm7_shared_ringbuffer = match unsafe {
         shared_ringbuffer::SharedRingBufferWithCS::<1024, 8, HardwareCriticalSection>
-            ::assign(CM4_TO_CM7_SHARED_RINGBUFFER,
+            ::attach(CM4_TO_CM7_SHARED_RINGBUFFER,
                      CM4_TO_CM7_SHARED_RINGBUFFER_SIZE,
                      hwcs)
+    } {
+        Ok(b) => b,
+        Err(err) => {
+            panic!("failed to attach cm4 to cm7 ring buffer. {}", err);
+        }
     };
 
     let gpiob = dp.GPIOB.split(ccdr.peripheral.GPIOB);
@@ -178,7 +191,7 @@ fn main() -> ! {
     let mut console =
         unsafe {
             console::Console::new(
-                &mut CONSOLE_BUFFER,
+                &mut *core::ptr::addr_of_mut!(CONSOLE_BUFFER),
                 "cm7> ",
                 move || {
                     match usart_rx.read() {
@@ -192,13 +205,13 @@ fn main() -> ! {
                     block!(usart_tx.write(c)).ok();
                 },
                 Some(move |command:&str| {
-                    if sem2.take(1) {
-                        debug!("send {} <{}>", command.len(), command);
-                        let _ = cm7_to_cm4_shared_ringbuffer.write(command.as_bytes());
-                        sem2.release(1);
+                    debug!("send {} <{}>", command.len(), command);
+                    if let Err(e) = cm7_to_cm4_shared_ringbuffer.write(command.as_bytes()) {
+                        debug!("send error: {}", e);
                     }
                 }))
         };
+    console.set_history(unsafe { history::History::new(&mut *core::ptr::addr_of_mut!(HISTORY_BUFFER), 8) });
 
     let mut prev_blink = false;
     loop {
@@ -217,13 +230,14 @@ fn main() -> ! {
             prev_blink = current;
         });
 
-        console.input();
+        let _ = console.input();
 
         {
             let mut recvbuf = [0u8;1024];
             match cm4_to_cm7_shared_ringbuffer.read(&mut recvbuf) {
-                Ok(_readsize) => {
-                    let _ = write!(console,">cm4> {}\r\n", core::str::from_utf8(&recvbuf).unwrap());
+                Ok(readsize) => match core::str::from_utf8(&recvbuf[..readsize]) {
+                    Ok(message) => { let _ = console.write_above(format_args!(">cm4> {}", message)); },
+                    Err(_) => debug!("message is not UTF-8"),
                 },
                 Err(shared_ringbuffer::SharedRingBufferError::NoData) => {},
                 Err(e) => { debug!("read error: {}", e); }